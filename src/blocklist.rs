@@ -0,0 +1,258 @@
+use std::collections::HashMap;
+use std::fmt::{Display, Formatter};
+use std::path::PathBuf;
+use std::str::FromStr;
+use std::time::Duration;
+
+use serde::{Deserialize, Serialize};
+
+use crate::error::ConfigError;
+use crate::mpris::SongAttributes;
+
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct BlockList {
+    // URL rules are by far the most common rules, so they are indexed by their (normalized) URL.
+    url_rules: HashMap<String, BlockRule>,
+    other_rules: Vec<BlockRule>,
+    // Entries that could not be parsed and were therefore skipped.
+    errors: Vec<ConfigError>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct BlockRule {
+    pub kind: RuleKind,
+    pub source: RuleSource,
+    // Only skip the song if it was already played once without being played in full.
+    pub give_one_chance: bool,
+    // Overrides the block_action setting for this rule.
+    pub action: Option<BlockAction>,
+}
+
+// What to do when a song is blocked.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum BlockAction {
+    // Skip to the next song.
+    Skip,
+    Pause,
+    // Mute the player until the next song starts.
+    Mute,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum RuleKind {
+    Url(String),
+    // Matches all songs shorter than the given duration.
+    MinDuration(Duration),
+    // Matches all songs longer than the given duration.
+    MaxDuration(Duration),
+    // Matches all songs whose title contains the given (lowercase) word or phrase.
+    TitleKeyword(String),
+    // Matches all songs for which all of the given attributes match.
+    Conditions {
+        url: Option<String>,
+        artist: Option<String>,
+        title: Option<String>,
+    },
+}
+
+// Where a rule was defined, so that we can tell users why a song was blocked.
+#[derive(Debug, Clone, PartialEq)]
+pub enum RuleSource {
+    ConfigFile { path: PathBuf, line_number: usize },
+    TomlConfigFile { path: PathBuf, entry_number: usize },
+    Stdin { line_number: usize },
+}
+
+impl BlockList {
+    pub fn insert(&mut self, rule: BlockRule) {
+        match &rule.kind {
+            RuleKind::Url(url) => {
+                self.url_rules.entry(url.clone()).or_insert(rule);
+            }
+            _ => self.other_rules.push(rule),
+        }
+    }
+
+    pub fn add_error(&mut self, error: ConfigError) {
+        error!("{}", error);
+        self.errors.push(error);
+    }
+
+    pub fn errors(&self) -> &[ConfigError] {
+        &self.errors
+    }
+
+    pub fn rules(&self) -> impl Iterator<Item = &BlockRule> {
+        self.url_rules.values().chain(self.other_rules.iter())
+    }
+
+    pub fn extend(&mut self, other: &BlockList) {
+        for rule in other.rules() {
+            self.insert(rule.clone());
+        }
+        self.errors.extend(other.errors.iter().cloned());
+    }
+
+    pub fn len(&self) -> usize {
+        self.url_rules.len() + self.other_rules.len()
+    }
+
+    // Expects the song's URL to be normalized already.
+    pub fn find_match(&self, song: &SongAttributes) -> Option<&BlockRule> {
+        self.url_rules
+            .get(&song.url)
+            .or_else(|| {
+                // Also match the URL rules against the track ID, in case the player sends an
+                // unusual URL.
+                let track_id = song.track_id.as_ref()?;
+                self.url_rules
+                    .get(&format!("https://open.spotify.com/track/{}", track_id))
+            })
+            .or_else(|| self.other_rules.iter().find(|rule| rule.kind.matches(song)))
+    }
+}
+
+impl FromStr for BlockAction {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "skip" => Ok(BlockAction::Skip),
+            "pause" => Ok(BlockAction::Pause),
+            "mute" => Ok(BlockAction::Mute),
+            other => Err(format!(
+                "unknown action '{}', expected one of: skip, pause, mute",
+                other
+            )),
+        }
+    }
+}
+
+impl Display for BlockAction {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        let action = match self {
+            BlockAction::Skip => "skip",
+            BlockAction::Pause => "pause",
+            BlockAction::Mute => "mute",
+        };
+        write!(f, "{}", action)
+    }
+}
+
+impl RuleKind {
+    fn matches(&self, song: &SongAttributes) -> bool {
+        match self {
+            RuleKind::Url(url) => &song.url == url,
+            RuleKind::MinDuration(min) => song.length.is_some_and(|length| length < *min),
+            RuleKind::MaxDuration(max) => song.length.is_some_and(|length| length > *max),
+            RuleKind::TitleKeyword(keyword) => song
+                .title
+                .as_ref()
+                .is_some_and(|title| contains_word(&title.to_lowercase(), keyword)),
+            RuleKind::Conditions { url, artist, title } => {
+                url.as_ref().is_none_or(|url| &song.url == url)
+                    && artist
+                        .as_ref()
+                        .is_none_or(|artist| artist_matches(song, artist))
+                    && title.as_ref().is_none_or(|title| {
+                        song.title
+                            .as_ref()
+                            .is_some_and(|t| t.to_lowercase() == title.to_lowercase())
+                    })
+            }
+        }
+    }
+}
+
+// Songs with multiple artists match if any of their artists matches.
+fn artist_matches(song: &SongAttributes, artist: &str) -> bool {
+    let artist = artist.to_lowercase();
+    song.artist.as_ref().is_some_and(|artists| {
+        let artists = artists.to_lowercase();
+        artists == artist || artists.split(", ").any(|a| a == artist)
+    })
+}
+
+// Only matches whole words, so that e.g. the keyword "ass" does not match the title "Bass".
+fn contains_word(haystack: &str, word: &str) -> bool {
+    haystack.match_indices(word).any(|(start, _)| {
+        let end = start + word.len();
+        let boundary_before = haystack[..start]
+            .chars()
+            .next_back()
+            .is_none_or(|c| !c.is_alphanumeric());
+        let boundary_after = haystack[end..]
+            .chars()
+            .next()
+            .is_none_or(|c| !c.is_alphanumeric());
+        boundary_before && boundary_after
+    })
+}
+
+// Parses durations like "30s", "5m" or "1m30s".
+pub fn parse_duration(s: &str) -> Result<Duration, String> {
+    let mut total_secs = 0;
+    let mut digits = String::new();
+    for c in s.chars() {
+        if c.is_ascii_digit() {
+            digits.push(c);
+            continue;
+        }
+        let factor = match c {
+            's' => 1,
+            'm' => 60,
+            'h' => 60 * 60,
+            _ => return Err(format!("invalid duration: {}", s)),
+        };
+        let value: u64 = digits
+            .parse()
+            .map_err(|_| format!("invalid duration: {}", s))?;
+        total_secs += value * factor;
+        digits.clear();
+    }
+    if !digits.is_empty() || s.is_empty() {
+        return Err(format!(
+            "invalid duration: {} (expected something like 30s, 5m or 1m30s)",
+            s
+        ));
+    }
+
+    Ok(Duration::from_secs(total_secs))
+}
+
+impl Display for RuleKind {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            RuleKind::Url(url) => write!(f, "{}", url),
+            RuleKind::MinDuration(d) => write!(f, "min_duration:{}s", d.as_secs()),
+            RuleKind::MaxDuration(d) => write!(f, "max_duration:{}s", d.as_secs()),
+            RuleKind::TitleKeyword(keyword) => write!(f, "title keyword \"{}\"", keyword),
+            RuleKind::Conditions { url, artist, title } => {
+                let conditions: Vec<String> = [
+                    url.as_ref().map(|u| format!("url = {}", u)),
+                    artist.as_ref().map(|a| format!("artist = \"{}\"", a)),
+                    title.as_ref().map(|t| format!("title = \"{}\"", t)),
+                ]
+                .into_iter()
+                .flatten()
+                .collect();
+                write!(f, "{}", conditions.join(" and "))
+            }
+        }
+    }
+}
+
+impl Display for RuleSource {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            RuleSource::ConfigFile { path, line_number } => {
+                write!(f, "{}, line {}", path.display(), line_number)
+            }
+            RuleSource::TomlConfigFile { path, entry_number } => {
+                write!(f, "{}, [[block]] entry {}", path.display(), entry_number)
+            }
+            RuleSource::Stdin { line_number } => write!(f, "stdin, line {}", line_number),
+        }
+    }
+}
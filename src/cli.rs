@@ -0,0 +1,32 @@
+use std::env;
+
+#[derive(Debug, Default)]
+pub struct Arguments {
+    // Read additional blocked songs from stdin upon start.
+    pub blocklist_stdin: bool,
+    // Run with a separate set of configuration files and socket.
+    pub profile: Option<String>,
+    // Only check the config files for errors instead of running audiowarden.
+    pub check_config: bool,
+}
+
+pub fn parse_arguments() -> Result<Arguments, String> {
+    let mut arguments = Arguments::default();
+    let mut args = env::args().skip(1);
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--blocklist-stdin" => arguments.blocklist_stdin = true,
+            "--check-config" => arguments.check_config = true,
+            "--profile" => match args.next() {
+                Some(profile) => arguments.profile = Some(profile),
+                None => return Err("Missing value for argument --profile".to_string()),
+            },
+            other => match other.strip_prefix("--profile=") {
+                Some(profile) => arguments.profile = Some(profile.to_string()),
+                None => return Err(format!("Unknown argument: {}", other)),
+            },
+        }
+    }
+
+    Ok(arguments)
+}
@@ -1,80 +1,549 @@
-use std::collections::HashSet;
 use std::fs::{File, OpenOptions};
 use std::io::{BufRead, BufReader, BufWriter, Error, ErrorKind, Write};
 use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex, OnceLock};
+use std::time::SystemTime;
 use std::{env, fs, io};
 
-use crate::APPLICATION_NAME;
+use crate::blocklist::{parse_duration, BlockAction, BlockList, BlockRule, RuleKind, RuleSource};
+use crate::error::{AudioWardenError, ConfigError};
+use crate::{profile, settings, summary, APPLICATION_NAME};
+use serde::Deserialize;
 use url::Url;
 
-pub fn get_blocked_songs() -> Result<HashSet<String>, Error> {
-    let path = create_config_path_and_file();
-    parse_config_file(&path)
-}
-
-fn create_config_path_and_file() -> PathBuf {
-    match get_config_path() {
-        Ok(config_path) => {
-            let filepath = config_path.join("blocked_songs.conf");
-            match fs::create_dir_all(&config_path) {
-                Ok(_) => {
-                    create_initial_config_file(&filepath);
-                }
-                Err(e) => {
-                    if e.kind() == ErrorKind::AlreadyExists {
-                        // config directory already exists: this is the expected case when
-                        // the application is not running for the first time and the config dir
-                        // was therefore already created previously.
-                    } else {
-                        panic!(
-                            "Unable to create config directory at {:?}: {:?}",
-                            &config_path, e
-                        );
-                    }
-                }
+// Contains both the settings (see the settings module) and, optionally, the blocked songs in a
+// more structured format than blocked_songs.conf.
+pub const TOML_CONFIG_FILENAME: &str = "config.toml";
+
+pub const BLOCKED_SONGS_FILENAME: &str = "blocked_songs.conf";
+
+const ALLOWED_SONGS_FILENAME: &str = "allowed_songs.conf";
+
+// Every *.conf file in this directory is read in addition to blocked_songs.conf.
+pub const CONF_D_DIRNAME: &str = "blocked_songs.conf.d";
+
+// Blocked songs that were supplied upon start (e.g. via stdin) instead of the config file.
+static STARTUP_BLOCKED_SONGS: OnceLock<BlockList> = OnceLock::new();
+
+static ENTRIES_TRUNCATED_WARNING_LOGGED: AtomicBool = AtomicBool::new(false);
+
+static CONFIG_UNAVAILABLE_WARNING_LOGGED: AtomicBool = AtomicBool::new(false);
+
+static BLOCKED_SONGS_CACHE: Mutex<Option<CachedBlockList>> = Mutex::new(None);
+
+type FileVersion = (PathBuf, Option<(SystemTime, u64)>);
+
+struct CachedBlockList {
+    file_versions: Vec<FileVersion>,
+    includes_startup_songs: bool,
+    block_list: Arc<BlockList>,
+}
+
+#[derive(Debug, Deserialize)]
+struct TomlConfig {
+    #[serde(default)]
+    block: Vec<TomlBlockEntry>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct TomlBlockEntry {
+    url: Option<String>,
+    artist: Option<String>,
+    title: Option<String>,
+    #[serde(default)]
+    give_one_chance: bool,
+    action: Option<BlockAction>,
+}
+
+enum TomlBlockEntries {
+    // The TOML config file doesn't exist or doesn't contain any [[block]] entries.
+    Missing,
+    Invalid(ConfigError),
+    Valid(BlockList),
+}
+
+pub fn get_blocked_songs() -> Result<Arc<BlockList>, Error> {
+    let path = match create_config_path_and_file() {
+        Ok(path) => Some(path),
+        Err(e) => {
+            // E.g. a read-only home directory: We can still use the songs supplied on startup.
+            if !CONFIG_UNAVAILABLE_WARNING_LOGGED.swap(true, Ordering::Relaxed) {
+                warn!(
+                    "{}. Blocked songs from the config directory are disabled.",
+                    e
+                );
             }
-            filepath
+            None
+        }
+    };
+    let keywords_path = title_keywords_path(path.as_deref().and_then(Path::parent));
+    read_config_files_cached(path.as_deref(), keywords_path.as_deref())
+}
+
+// Used by --check-config: Unlike get_blocked_songs, this neither creates the config directory
+// nor the initial config file, and doesn't use the cache.
+pub fn check_blocked_songs() -> Result<BlockList, Error> {
+    let config_dir = get_config_path().map_err(Error::other)?;
+    let path = config_dir.join(BLOCKED_SONGS_FILENAME);
+    let keywords_path = title_keywords_path(Some(&config_dir));
+    read_all_blocked_songs(Some(&path), keywords_path.as_deref())
+}
+
+// Relative paths are relative to the config directory.
+pub fn title_keywords_path(config_dir: Option<&Path>) -> Option<PathBuf> {
+    settings::get()
+        .title_keywords_file
+        .as_ref()
+        .map(|keywords_path| match config_dir {
+            Some(config_dir) => config_dir.join(keywords_path),
+            None => keywords_path.clone(),
+        })
+}
+
+// Parsing the config files for every song is slow for large blocklists, so the result is cached
+// until one of the files changes. The block list is shared instead of copied, since it's needed
+// for every signal.
+fn read_config_files_cached(
+    path: Option<&Path>,
+    keywords_path: Option<&Path>,
+) -> Result<Arc<BlockList>, Error> {
+    if !settings::get().cache_blocked_songs {
+        return read_all_blocked_songs(path, keywords_path).map(Arc::new);
+    }
+    let file_versions = file_versions(path, keywords_path);
+    // The songs from stdin may be read after the cache has been filled.
+    let includes_startup_songs = STARTUP_BLOCKED_SONGS.get().is_some();
+    let mut cache = BLOCKED_SONGS_CACHE.lock().unwrap();
+    if let Some(cached) = cache.as_ref().filter(|cached| {
+        cached.file_versions == file_versions
+            && cached.includes_startup_songs == includes_startup_songs
+    }) {
+        return Ok(cached.block_list.clone());
+    }
+    debug!("Config files have changed, reading blocked songs.");
+    summary::config_reloaded();
+    let block_list = Arc::new(read_all_blocked_songs(path, keywords_path)?);
+    *cache = Some(CachedBlockList {
+        file_versions,
+        includes_startup_songs,
+        block_list: block_list.clone(),
+    });
+    Ok(block_list)
+}
+
+fn read_all_blocked_songs(
+    path: Option<&Path>,
+    keywords_path: Option<&Path>,
+) -> Result<BlockList, Error> {
+    let mut blocked_songs = read_config_files(path, keywords_path)?;
+    if let Some(startup_blocked_songs) = STARTUP_BLOCKED_SONGS.get() {
+        blocked_songs.extend(startup_blocked_songs);
+    }
+    Ok(blocked_songs)
+}
+
+fn read_config_files(
+    path: Option<&Path>,
+    keywords_path: Option<&Path>,
+) -> Result<BlockList, Error> {
+    let mut blocked_songs = match path {
+        Some(path) => {
+            let mut blocked_songs = parse_blocked_songs_config_files(path)?;
+            blocked_songs.extend(&parse_conf_d_dir(&path.with_file_name(CONF_D_DIRNAME))?);
+            blocked_songs
+        }
+        None => BlockList::default(),
+    };
+    if let Some(keywords_path) = keywords_path {
+        blocked_songs.extend(&parse_title_keywords_file(keywords_path));
+    }
+    Ok(blocked_songs)
+}
+
+// The modification time and size of all files (and directories) the blocked songs are read from,
+// or None if a file doesn't exist.
+fn file_versions(path: Option<&Path>, keywords_path: Option<&Path>) -> Vec<FileVersion> {
+    let mut paths = vec![];
+    if let Some(path) = path {
+        paths.push(path.to_path_buf());
+        paths.push(path.with_file_name(TOML_CONFIG_FILENAME));
+        let conf_d_path = path.with_file_name(CONF_D_DIRNAME);
+        if let Ok(entries) = fs::read_dir(&conf_d_path) {
+            paths.extend(
+                entries
+                    .filter_map(|entry| entry.ok())
+                    .map(|entry| entry.path()),
+            );
         }
+        paths.push(conf_d_path);
+    }
+    paths.extend(keywords_path.map(Path::to_path_buf));
+    paths.sort();
+    paths
+        .into_iter()
+        .map(|path| {
+            let version = fs::metadata(&path)
+                .and_then(|metadata| Ok((metadata.modified()?, metadata.len())))
+                .ok();
+            (path, version)
+        })
+        .collect()
+}
+
+fn parse_blocked_songs_config_files(path: &Path) -> Result<BlockList, Error> {
+    let toml_path = path.with_file_name(TOML_CONFIG_FILENAME);
+    let blocked_songs = match parse_toml_config_file(&toml_path)? {
+        TomlBlockEntries::Valid(blocked_songs) => blocked_songs,
+        TomlBlockEntries::Missing => parse_optional_config_file(path)?,
+        TomlBlockEntries::Invalid(e) => {
+            let mut blocked_songs = parse_optional_config_file(path)?;
+            blocked_songs.add_error(e);
+            blocked_songs
+        }
+    };
+    Ok(blocked_songs)
+}
+
+// Reads all *.conf files in the given directory, so that packages or scripts can add their own
+// blocklists without editing blocked_songs.conf.
+fn parse_conf_d_dir(dir: &Path) -> Result<BlockList, Error> {
+    let mut block_list = BlockList::default();
+    let entries = match fs::read_dir(dir) {
+        Ok(entries) => entries,
+        Err(e) if e.kind() == ErrorKind::NotFound => return Ok(block_list),
+        Err(e) => return Err(e),
+    };
+    let mut paths = vec![];
+    for entry in entries {
+        let path = entry?.path();
+        if path.is_file() && path.extension().is_some_and(|ext| ext == "conf") {
+            paths.push(path);
+        }
+    }
+    // Sorted, so that the order doesn't depend on the file system.
+    paths.sort();
+    for path in paths {
+        let file_block_list = match parse_config_file(&path) {
+            Ok(file_block_list) => file_block_list,
+            Err(e) => {
+                // One unreadable file shouldn't prevent all other files from being used.
+                warn!("Unable to read {}: {}", path.display(), e);
+                continue;
+            }
+        };
+        debug!(
+            "Loaded {} rules from {}",
+            file_block_list.len(),
+            path.display()
+        );
+        block_list.extend(&file_block_list);
+    }
+    Ok(block_list)
+}
+
+fn parse_title_keywords_file(path: &Path) -> BlockList {
+    let mut block_list = BlockList::default();
+    let file = match File::open(path) {
+        Ok(file) => file,
         Err(e) => {
-            panic!("Unable to fetch config directory: {}", e);
+            block_list.add_error(ConfigError::KeywordsFile {
+                path: path.to_path_buf(),
+                message: e.to_string(),
+            });
+            return block_list;
         }
+    };
+
+    for (line_number, line) in BufReader::new(file).lines().enumerate() {
+        let line = match line {
+            Ok(line) => line,
+            Err(e) => {
+                block_list.add_error(ConfigError::KeywordsFile {
+                    path: path.to_path_buf(),
+                    message: e.to_string(),
+                });
+                break;
+            }
+        };
+        let line = trim_line(&line, line_number);
+        // The # char may be used for comments.
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        block_list.insert(BlockRule {
+            kind: RuleKind::TitleKeyword(line.to_lowercase()),
+            source: RuleSource::ConfigFile {
+                path: path.to_path_buf(),
+                line_number: line_number + 1,
+            },
+            give_one_chance: false,
+            action: None,
+        });
     }
+
+    block_list
 }
 
-fn parse_config_file(path: &Path) -> Result<HashSet<String>, Error> {
+// Only used in allowlist mode: Contains the songs that may be played, all other songs are skipped.
+// Unlike the blocked songs, the allowed songs are neither cached nor read from a conf.d directory:
+// allowlists are usually short.
+pub fn get_allowed_songs() -> Result<BlockList, Error> {
+    let path = create_config_path_and_file()
+        .map_err(|e| Error::other(e.to_string()))?
+        .with_file_name(ALLOWED_SONGS_FILENAME);
+    parse_config_file(&path).map_err(|e| Error::new(e.kind(), format!("{}: {}", path.display(), e)))
+}
+
+pub fn read_blocked_songs_from_stdin() -> Result<usize, Error> {
+    let blocked_songs = parse_blocked_songs(io::stdin().lock(), |line_number| RuleSource::Stdin {
+        line_number,
+    })?;
+    let num_songs = blocked_songs.len();
+    if STARTUP_BLOCKED_SONGS.set(blocked_songs).is_err() {
+        warn!("Blocked songs from stdin have already been read.");
+    }
+    Ok(num_songs)
+}
+
+fn create_config_path_and_file() -> Result<PathBuf, AudioWardenError> {
+    let config_path = get_config_path()?;
+    let filepath = config_path.join(BLOCKED_SONGS_FILENAME);
+    match fs::create_dir_all(&config_path) {
+        Ok(()) => {
+            create_initial_config_file(&filepath);
+        }
+        Err(e) if e.kind() == ErrorKind::AlreadyExists => {
+            // config directory already exists: this is the expected case when
+            // the application is not running for the first time and the config dir
+            // was therefore already created previously.
+        }
+        Err(e) => {
+            return Err(AudioWardenError::GenericError(format!(
+                "Unable to create config directory at {}: {}",
+                config_path.display(),
+                e
+            )));
+        }
+    }
+    Ok(filepath)
+}
+
+fn parse_config_file(path: &Path) -> Result<BlockList, Error> {
     let file = File::open(path)?;
-    let reader = BufReader::new(file);
-    let mut valid_urls = HashSet::new();
+    parse_blocked_songs(BufReader::new(file), |line_number| RuleSource::ConfigFile {
+        path: path.to_path_buf(),
+        line_number,
+    })
+}
+
+// blocked_songs.conf is usually created on start, but not by --check-config.
+fn parse_optional_config_file(path: &Path) -> Result<BlockList, Error> {
+    match parse_config_file(path) {
+        Err(e) if e.kind() == ErrorKind::NotFound => Ok(BlockList::default()),
+        result => result,
+    }
+}
+
+fn parse_blocked_songs<R, F>(reader: R, source: F) -> Result<BlockList, Error>
+where
+    R: BufRead,
+    F: Fn(usize) -> RuleSource,
+{
+    let mut block_list = BlockList::default();
+    let mut entries = 0;
 
     for (line_number, line) in reader.lines().enumerate() {
         let line = line?;
-        let line = line.trim();
+        let line = trim_line(&line, line_number);
 
         // The # char may be used for comments.
         if line.is_empty() || line.starts_with('#') {
             continue;
         }
 
-        if let Ok(mut url) = Url::parse(line) {
-            // When we copy URLs from spotify (via "share" in the context menu), then the resulting
-            // link usually has a query param attached to it, something like '?si=7764fc…'. But
-            // the URLs we get via mpris/dbus do not contain this query param. Therefore, we need
-            // to remove it so that songs are matched correctly.
-            url.set_query(None);
-            valid_urls.insert(url.to_string());
-        } else {
-            error!(
-                "Error in line {}: the following is not a valid URL: {}",
-                line_number + 1,
-                line
-            );
+        // Counts lines instead of rules, so that duplicate and invalid lines also count towards
+        // the limit.
+        if entries >= settings::get().max_entries_per_file {
+            warn_entries_truncated(&source(line_number + 1));
+            break;
+        }
+        entries += 1;
+
+        let source = source(line_number + 1);
+        let rule = parse_action(line, &source).and_then(|(line, action)| {
+            let kind = parse_rule(line, &source)?;
+            Ok(BlockRule {
+                kind,
+                source: source.clone(),
+                give_one_chance: false,
+                action,
+            })
+        });
+        match rule {
+            Ok(rule) => block_list.insert(rule),
+            Err(e) => block_list.add_error(e),
         }
     }
 
-    Ok(valid_urls)
+    Ok(block_list)
+}
+
+// Files saved by some editors (mostly on Windows) start with a byte order mark, which isn't
+// removed by trim() and would make the first rule invalid. Trailing CRs of CRLF line endings are
+// already removed by lines(), but we remove them here as well to be explicit.
+fn trim_line(line: &str, line_number: usize) -> &str {
+    let line = if line_number == 0 {
+        line.strip_prefix('\u{feff}').unwrap_or(line)
+    } else {
+        line
+    };
+    line.trim_end_matches('\r').trim()
+}
+
+// Lines may end with an action like "action=mute", which overrides the block_action setting.
+fn parse_action<'a>(
+    line: &'a str,
+    source: &RuleSource,
+) -> Result<(&'a str, Option<BlockAction>), ConfigError> {
+    let (rule, action) = match line
+        .rsplit_once(char::is_whitespace)
+        .and_then(|(rule, action)| Some((rule, action.strip_prefix("action=")?)))
+    {
+        Some((rule, action)) => (rule, action),
+        None => return Ok((line, None)),
+    };
+    let action = action.parse().map_err(|message| ConfigError::Action {
+        source: source.clone(),
+        message,
+    })?;
+    Ok((rule.trim_end(), Some(action)))
+}
+
+fn parse_rule(line: &str, source: &RuleSource) -> Result<RuleKind, ConfigError> {
+    let invalid_duration = |message| ConfigError::Duration {
+        source: source.clone(),
+        message,
+    };
+    if let Some(duration) = line.strip_prefix("min_duration:") {
+        parse_duration(duration.trim())
+            .map(RuleKind::MinDuration)
+            .map_err(invalid_duration)
+    } else if let Some(duration) = line.strip_prefix("max_duration:") {
+        parse_duration(duration.trim())
+            .map(RuleKind::MaxDuration)
+            .map_err(invalid_duration)
+    } else {
+        normalize_url(line)
+            .map(RuleKind::Url)
+            .map_err(|_| ConfigError::Url {
+                source: source.clone(),
+                url: line.to_string(),
+            })
+    }
+}
+
+fn warn_entries_truncated(source: &RuleSource) {
+    // The config files are parsed for every song, so we only warn once to avoid flooding the logs.
+    if !ENTRIES_TRUNCATED_WARNING_LOGGED.swap(true, Ordering::Relaxed) {
+        warn!(
+            "Too many entries: Ignoring all entries starting at {}. The limit can be raised \
+                with the max_entries_per_file setting.",
+            source
+        );
+    }
+}
+
+// Unless the TOML config file contains valid [[block]] entries, blocked_songs.conf is used instead.
+fn parse_toml_config_file(path: &Path) -> Result<TomlBlockEntries, Error> {
+    let content = match fs::read_to_string(path) {
+        Ok(content) => content,
+        Err(e) if e.kind() == ErrorKind::NotFound => return Ok(TomlBlockEntries::Missing),
+        Err(e) => return Err(e),
+    };
+    let toml_config: TomlConfig = match toml::from_str(&content) {
+        Ok(toml_config) => toml_config,
+        Err(e) => {
+            return Ok(TomlBlockEntries::Invalid(ConfigError::TomlSyntax {
+                path: path.to_path_buf(),
+                message: format!("{} (using blocked_songs.conf instead)", e.message()),
+            }));
+        }
+    };
+    if toml_config.block.is_empty() {
+        return Ok(TomlBlockEntries::Missing);
+    }
+
+    let mut block_list = BlockList::default();
+    for (entry_number, entry) in toml_config.block.iter().enumerate() {
+        let source = RuleSource::TomlConfigFile {
+            path: path.to_path_buf(),
+            entry_number: entry_number + 1,
+        };
+        if entry_number >= settings::get().max_entries_per_file {
+            warn_entries_truncated(&source);
+            break;
+        }
+
+        match rule_from_toml_entry(entry, &source) {
+            Ok(kind) => block_list.insert(BlockRule {
+                kind,
+                source,
+                give_one_chance: entry.give_one_chance,
+                action: entry.action,
+            }),
+            Err(e) => block_list.add_error(e),
+        }
+    }
+
+    Ok(TomlBlockEntries::Valid(block_list))
+}
+
+// All fields of an entry must match for the rule to match.
+fn rule_from_toml_entry(
+    entry: &TomlBlockEntry,
+    source: &RuleSource,
+) -> Result<RuleKind, ConfigError> {
+    let url = match &entry.url {
+        Some(url) => Some(normalize_url(url).map_err(|_| ConfigError::Url {
+            source: source.clone(),
+            url: url.clone(),
+        })?),
+        None => None,
+    };
+    match (url, &entry.artist, &entry.title) {
+        (None, None, None) => Err(ConfigError::EmptyBlockEntry {
+            source: source.clone(),
+        }),
+        (Some(url), None, None) => Ok(RuleKind::Url(url)),
+        (url, artist, title) => Ok(RuleKind::Conditions {
+            url,
+            artist: artist.clone(),
+            title: title.clone(),
+        }),
+    }
+}
+
+// All URLs (from the config files as well as from MPRIS) must be normalized with this function
+// before they are compared, so that the same policy applies everywhere.
+pub fn normalize_url(url: &str) -> Result<String, url::ParseError> {
+    let mut url = Url::parse(url)?;
+    if !settings::get().keep_url_query {
+        // When we copy URLs from spotify (via "share" in the context menu), then the resulting
+        // link usually has a query param attached to it, something like '?si=7764fc…'. But
+        // the URLs we get via mpris/dbus do not contain this query param. Therefore, we need
+        // to remove it so that songs are matched correctly.
+        url.set_query(None);
+    }
+    Ok(url.to_string())
 }
 
 pub fn get_config_path() -> Result<PathBuf, String> {
+    get_base_config_path().map(|path| profile::namespaced_path(&path))
+}
+
+fn get_base_config_path() -> Result<PathBuf, String> {
     if let Ok(config_dir) = env::var("CONFIGURATION_DIRECTORY") {
         // CONFIGURATION_DIRECTORY is set if this application runs via systemd: More details here:
         // https://www.freedesktop.org/software/systemd/man/latest/systemd.exec.html#RuntimeDirectory=
@@ -119,10 +588,77 @@ fn create_initial_config_file(path: &Path) {
     }
 }
 
-pub fn add_to_config_file(content: &str) -> io::Result<()> {
-    let path = create_config_path_and_file();
-    let file = OpenOptions::new().append(true).open(path)?;
+// If a list is given, the song is added to the file with that name in blocked_songs.conf.d
+// instead of the main config file.
+pub fn add_blocked_song(url: &str, comment: Option<&str>, list: Option<&str>) -> io::Result<()> {
+    let path = create_config_path_and_file().map_err(|e| Error::other(e.to_string()))?;
+    let toml_path = path.with_file_name(TOML_CONFIG_FILENAME);
+    let comment = match comment {
+        Some(c) => format!("# {}\n", c),
+        None => "".to_string(),
+    };
+    if let Some(list) = list {
+        if list.is_empty() || list.starts_with('.') || list.contains('/') {
+            return Err(Error::new(
+                ErrorKind::InvalidInput,
+                format!("invalid list name: {:?}", list),
+            ));
+        }
+        let conf_d_path = path.with_file_name(CONF_D_DIRNAME);
+        fs::create_dir_all(&conf_d_path)?;
+        append_to_file(
+            &conf_d_path.join(format!("{}.conf", list)),
+            &format!("\n{}{}\n", comment, url),
+        )
+    } else if let TomlBlockEntries::Valid(_) = parse_toml_config_file(&toml_path)? {
+        // The TOML config file takes precedence, so the song needs to be added there in order to
+        // be blocked.
+        let url = toml::Value::String(url.to_string());
+        append_to_file(
+            &toml_path,
+            &format!("\n[[block]]\n{}url = {}\n", comment, url),
+        )
+    } else {
+        append_to_file(&path, &format!("\n{}{}\n", comment, url))
+    }
+}
+
+fn append_to_file(path: &Path, content: &str) -> io::Result<()> {
+    let file = OpenOptions::new().append(true).create(true).open(path)?;
     let mut writer = BufWriter::new(file);
     writer.write_all(content.as_bytes())?;
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Cursor;
+
+    #[test]
+    fn parse_blocked_songs_with_bom_and_crlf() {
+        let content =
+            "\u{feff}https://open.spotify.com/track/X\r\nhttps://open.spotify.com/track/Y\r\n";
+        let block_list = parse_blocked_songs(Cursor::new(content), |line_number| {
+            RuleSource::Stdin { line_number }
+        })
+        .unwrap();
+
+        assert!(block_list.errors().is_empty(), "{:?}", block_list.errors());
+        let mut urls: Vec<&str> = block_list
+            .rules()
+            .filter_map(|rule| match &rule.kind {
+                RuleKind::Url(url) => Some(url.as_str()),
+                _ => None,
+            })
+            .collect();
+        urls.sort();
+        assert_eq!(
+            urls,
+            [
+                "https://open.spotify.com/track/X",
+                "https://open.spotify.com/track/Y"
+            ]
+        );
+    }
+}
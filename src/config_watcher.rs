@@ -0,0 +1,213 @@
+use std::ffi::{CString, OsStr, OsString};
+use std::fs::File;
+use std::io::Read;
+use std::mem::size_of;
+use std::os::fd::{AsRawFd, FromRawFd};
+use std::os::unix::ffi::OsStrExt;
+use std::path::Path;
+use std::sync::Arc;
+use std::{io, thread};
+
+use crate::blocklist::BlockList;
+use crate::{config, settings};
+
+// Editors often write files in several steps, so we only reload once there haven't been any
+// changes for this long.
+const DEBOUNCE_MILLIS: i32 = 500;
+
+// Each event is followed by its (variable length) file name.
+const EVENT_HEADER_SIZE: usize = size_of::<libc::inotify_event>();
+
+pub fn start() {
+    if !settings::get().watch_config {
+        return;
+    }
+    let path = match config::get_config_path() {
+        Ok(path) => path,
+        Err(e) => {
+            warn!("Unable to watch config directory: {}", e);
+            return;
+        }
+    };
+    thread::spawn(move || {
+        if let Err(e) = watch(&path) {
+            error!("Unable to watch config directory {}: {}", path.display(), e);
+        }
+    });
+}
+
+// The watch descriptors returned by inotify, so that we can tell which events are relevant.
+struct Watches {
+    config_dir: i32,
+    conf_d_dir: Option<i32>,
+    // The watch descriptor of the directory containing the title keywords file, and the file name.
+    keywords_file: Option<(i32, OsString)>,
+}
+
+impl Watches {
+    // Other files in the config directory (e.g. a status file) change frequently, which must not
+    // trigger a reload.
+    fn is_relevant(&self, wd: i32, name: &OsStr) -> bool {
+        let in_config_dir = wd == self.config_dir
+            && [
+                config::BLOCKED_SONGS_FILENAME,
+                config::TOML_CONFIG_FILENAME,
+                config::CONF_D_DIRNAME,
+            ]
+            .iter()
+            .any(|relevant_name| name == *relevant_name);
+        let in_conf_d_dir = Some(wd) == self.conf_d_dir
+            && Path::new(name).extension().is_some_and(|ext| ext == "conf");
+        let is_keywords_file =
+            self.keywords_file
+                .as_ref()
+                .is_some_and(|(keywords_wd, keywords_name)| {
+                    wd == *keywords_wd && name == keywords_name
+                });
+        in_config_dir || in_conf_d_dir || is_keywords_file
+    }
+}
+
+fn watch(path: &Path) -> io::Result<()> {
+    // The standard library doesn't support inotify, so we use it via libc.
+    let fd = unsafe { libc::inotify_init1(libc::IN_CLOEXEC) };
+    if fd < 0 {
+        return Err(io::Error::last_os_error());
+    }
+    // Closes the file descriptor when we're done.
+    let mut inotify = unsafe { File::from_raw_fd(fd) };
+    let conf_d_path = path.join(config::CONF_D_DIRNAME);
+    let mut watches = Watches {
+        config_dir: add_watch(&inotify, path)?,
+        conf_d_dir: None,
+        keywords_file: None,
+    };
+    if conf_d_path.is_dir() {
+        watches.conf_d_dir = Some(add_watch(&inotify, &conf_d_path)?);
+    }
+    // Editors usually replace files instead of writing to them, so we watch the directory.
+    if let Some(keywords_path) = config::title_keywords_path(Some(path)) {
+        let keywords_file = keywords_path.parent().zip(keywords_path.file_name());
+        if let Some((keywords_dir, keywords_name)) = keywords_file {
+            match add_watch(&inotify, keywords_dir) {
+                Ok(wd) => watches.keywords_file = Some((wd, keywords_name.to_os_string())),
+                Err(e) => warn!(
+                    "Unable to watch {} for changes: {}",
+                    keywords_dir.display(),
+                    e
+                ),
+            }
+        }
+    }
+    info!("Watching {} for changes.", path.display());
+
+    let mut blocked_songs = config::get_blocked_songs().ok();
+    let mut buffer = [0u8; 4096];
+    loop {
+        // Blocks until something has changed.
+        let mut is_relevant = read_events(&mut inotify, &mut buffer, &mut watches, &conf_d_path)?;
+        while has_pending_events(&inotify)? {
+            is_relevant |= read_events(&mut inotify, &mut buffer, &mut watches, &conf_d_path)?;
+        }
+        if is_relevant {
+            reload(&mut blocked_songs);
+        }
+    }
+}
+
+// Returns true if any of the events concerns a file the blocked songs are read from.
+fn read_events(
+    inotify: &mut File,
+    buffer: &mut [u8],
+    watches: &mut Watches,
+    conf_d_path: &Path,
+) -> io::Result<bool> {
+    let length = inotify.read(buffer)?;
+    if length == 0 {
+        return Err(io::Error::new(
+            io::ErrorKind::UnexpectedEof,
+            "inotify file descriptor was closed",
+        ));
+    }
+    let mut is_relevant = false;
+    let mut offset = 0;
+    while offset + EVENT_HEADER_SIZE <= length {
+        let event: libc::inotify_event =
+            unsafe { std::ptr::read_unaligned(buffer[offset..].as_ptr().cast()) };
+        let name_start = offset + EVENT_HEADER_SIZE;
+        let name_end = (name_start + event.len as usize).min(length);
+        // The name is padded with null bytes.
+        let name = buffer[name_start..name_end]
+            .split(|byte| *byte == 0)
+            .next()
+            .unwrap_or_default();
+        let name = OsStr::from_bytes(name);
+        offset = name_end;
+
+        let conf_d_created = event.wd == watches.config_dir
+            && event.mask & libc::IN_ISDIR != 0
+            && event.mask & (libc::IN_CREATE | libc::IN_MOVED_TO) != 0
+            && name == config::CONF_D_DIRNAME;
+        if conf_d_created {
+            // E.g. created by block_current_song with a list name.
+            match add_watch(inotify, conf_d_path) {
+                Ok(wd) => watches.conf_d_dir = Some(wd),
+                Err(e) => warn!(
+                    "Unable to watch {} for changes: {}",
+                    conf_d_path.display(),
+                    e
+                ),
+            }
+        }
+        is_relevant |= watches.is_relevant(event.wd, name);
+    }
+    Ok(is_relevant)
+}
+
+// Returns the watch descriptor.
+fn add_watch(inotify: &File, path: &Path) -> io::Result<i32> {
+    let c_path = CString::new(path.as_os_str().as_bytes())?;
+    let mask = libc::IN_CLOSE_WRITE
+        | libc::IN_MOVED_TO
+        | libc::IN_MOVED_FROM
+        | libc::IN_CREATE
+        | libc::IN_DELETE;
+    match unsafe { libc::inotify_add_watch(inotify.as_raw_fd(), c_path.as_ptr(), mask) } {
+        wd if wd < 0 => Err(io::Error::last_os_error()),
+        wd => Ok(wd),
+    }
+}
+
+// Waits up to DEBOUNCE_MILLIS for further events.
+fn has_pending_events(inotify: &File) -> io::Result<bool> {
+    let mut poll_fd = libc::pollfd {
+        fd: inotify.as_raw_fd(),
+        events: libc::POLLIN,
+        revents: 0,
+    };
+    match unsafe { libc::poll(&mut poll_fd, 1, DEBOUNCE_MILLIS) } {
+        n if n < 0 => Err(io::Error::last_os_error()),
+        n => Ok(n > 0),
+    }
+}
+
+// Only logs if the rules have actually changed, e.g. not if a file was saved without changes.
+fn reload(previous_blocked_songs: &mut Option<Arc<BlockList>>) {
+    match config::get_blocked_songs() {
+        Ok(blocked_songs) => {
+            if previous_blocked_songs.as_deref() == Some(&*blocked_songs) {
+                debug!("Config files have been written, but the rules haven't changed.");
+            } else {
+                info!(
+                    rules = blocked_songs.len(),
+                    errors = blocked_songs.errors().len();
+                    "Config files have changed: {} rules, {} errors.",
+                    blocked_songs.len(),
+                    blocked_songs.errors().len()
+                );
+            }
+            *previous_blocked_songs = Some(blocked_songs);
+        }
+        Err(e) => error!("Config files have changed, but can't be read: {}", e),
+    }
+}
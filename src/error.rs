@@ -1,4 +1,8 @@
+use std::fmt::{Display, Formatter};
 use std::io;
+use std::path::PathBuf;
+
+use crate::blocklist::RuleSource;
 
 #[derive(Debug)]
 pub enum AudioWardenError {
@@ -17,3 +21,55 @@ impl From<String> for AudioWardenError {
         AudioWardenError::GenericError(error)
     }
 }
+
+impl Display for AudioWardenError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            AudioWardenError::IoError(e) => write!(f, "I/O error: {}", e),
+            AudioWardenError::GenericError(e) => write!(f, "{}", e),
+        }
+    }
+}
+
+// An invalid entry in one of the config files. Invalid entries are skipped, so they don't prevent
+// the remaining entries from being used.
+#[derive(Debug, Clone, PartialEq)]
+pub enum ConfigError {
+    Url { source: RuleSource, url: String },
+    Duration { source: RuleSource, message: String },
+    TomlSyntax { path: PathBuf, message: String },
+    KeywordsFile { path: PathBuf, message: String },
+    EmptyBlockEntry { source: RuleSource },
+    Action { source: RuleSource, message: String },
+}
+
+impl Display for ConfigError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ConfigError::Url { source, url } => {
+                write!(f, "Error in {}: not a valid URL: {}", source, url)
+            }
+            ConfigError::Duration { source, message } | ConfigError::Action { source, message } => {
+                write!(f, "Error in {}: {}", source, message)
+            }
+            ConfigError::TomlSyntax { path, message } => {
+                write!(f, "Error in {}: {}", path.display(), message)
+            }
+            ConfigError::EmptyBlockEntry { source } => {
+                write!(
+                    f,
+                    "Error in {}: entry must contain at least one of url, artist or title",
+                    source
+                )
+            }
+            ConfigError::KeywordsFile { path, message } => {
+                write!(
+                    f,
+                    "Unable to read title keywords from {}: {}",
+                    path.display(),
+                    message
+                )
+            }
+        }
+    }
+}
@@ -0,0 +1,87 @@
+use std::env;
+use std::io::Write;
+
+use log::kv::{self, Key, Value, VisitSource, VisitValue};
+
+use crate::json;
+
+// The log format can't be part of the settings file, because the settings are loaded (and their
+// errors are logged) after the logger has been initialized.
+const LOG_FORMAT_ENV_VAR: &str = "AUDIOWARDEN_LOG_FORMAT";
+
+pub fn init() {
+    let mut builder = env_logger::Builder::from_default_env();
+    match env::var(LOG_FORMAT_ENV_VAR).as_deref() {
+        Ok("json") => {
+            // One JSON object per line, for log pipelines. Key-values of the record (e.g. the URL
+            // of a skipped song) are added as separate keys.
+            builder.format(|buf, record| {
+                let mut fields = JsonFields(String::new());
+                if let Err(e) = record.key_values().visit(&mut fields) {
+                    fields.0 = format!(",\"fields_error\":\"{}\"", json::escape(&e.to_string()));
+                }
+                writeln!(
+                    buf,
+                    "{{\"timestamp\":\"{}\",\"level\":\"{}\",\"module\":\"{}\",\"message\":\"{}\"{}}}",
+                    buf.timestamp(),
+                    record.level(),
+                    json::escape(record.module_path().unwrap_or_else(|| record.target())),
+                    json::escape(&record.args().to_string()),
+                    fields.0
+                )
+            });
+        }
+        Ok("text") | Err(_) => {}
+        Ok(other) => {
+            builder.init();
+            warn!(
+                "Unknown log format '{}' in {}, expected json or text. Using text.",
+                other, LOG_FORMAT_ENV_VAR
+            );
+            return;
+        }
+    }
+    builder.init();
+}
+
+// Serializes key-values as ,"key":value pairs, so that they can be appended to a JSON object.
+struct JsonFields(String);
+
+impl<'kvs> VisitSource<'kvs> for JsonFields {
+    fn visit_pair(&mut self, key: Key<'kvs>, value: Value<'kvs>) -> Result<(), kv::Error> {
+        self.0
+            .push_str(&format!(",\"{}\":", json::escape(key.as_str())));
+        value.visit(JsonValue(&mut self.0))
+    }
+}
+
+// Numbers, booleans and missing values (None) keep their JSON type, everything else is a string.
+struct JsonValue<'a>(&'a mut String);
+
+impl<'v> VisitValue<'v> for JsonValue<'_> {
+    fn visit_any(&mut self, value: Value) -> Result<(), kv::Error> {
+        self.0
+            .push_str(&format!("\"{}\"", json::escape(&value.to_string())));
+        Ok(())
+    }
+
+    fn visit_null(&mut self) -> Result<(), kv::Error> {
+        self.0.push_str("null");
+        Ok(())
+    }
+
+    fn visit_u64(&mut self, value: u64) -> Result<(), kv::Error> {
+        self.0.push_str(&value.to_string());
+        Ok(())
+    }
+
+    fn visit_i64(&mut self, value: i64) -> Result<(), kv::Error> {
+        self.0.push_str(&value.to_string());
+        Ok(())
+    }
+
+    fn visit_bool(&mut self, value: bool) -> Result<(), kv::Error> {
+        self.0.push_str(&value.to_string());
+        Ok(())
+    }
+}
@@ -3,13 +3,49 @@ extern crate log;
 
 use crate::mpris::setup_mpris_connection;
 
+mod blocklist;
+mod cli;
 mod config;
+mod config_watcher;
 mod error;
+mod json;
+mod logging;
 mod messaging;
+mod mode;
 mod mpris;
+mod one_chance;
+mod profile;
+mod quiet_hours;
+mod settings;
+mod skip_history;
+mod state;
+mod status_file;
+mod summary;
 
 fn main() {
-    env_logger::init();
+    logging::init();
+
+    let arguments = match cli::parse_arguments() {
+        Ok(arguments) => arguments,
+        Err(e) => {
+            eprintln!("{}", e);
+            std::process::exit(EXIT_CODE_INVALID_ARGUMENTS);
+        }
+    };
+
+    if let Some(name) = &arguments.profile {
+        if let Err(e) = profile::set_profile(name) {
+            eprintln!("{}", e);
+            std::process::exit(EXIT_CODE_INVALID_ARGUMENTS);
+        }
+        info!("Using profile: {}", name);
+    }
+
+    if arguments.check_config {
+        // check_config prints all errors itself, they would otherwise be printed twice.
+        log::set_max_level(log::LevelFilter::Off);
+        std::process::exit(check_config());
+    }
 
     messaging::setup_channel();
 
@@ -21,15 +57,80 @@ fn main() {
             info!("Configuration directory: {}", &path.display())
         }
         Err(e) => {
-            panic!("Unable to fetch config directory: {}", e);
+            warn!("Unable to fetch config directory: {}", e);
+        }
+    }
+    // Load the settings upon start, so that errors in the settings file are reported right away
+    // instead of when the first setting is accessed.
+    settings::get();
+
+    if arguments.blocklist_stdin {
+        match config::read_blocked_songs_from_stdin() {
+            Ok(num_songs) => info!("Read {} blocked songs from stdin.", num_songs),
+            Err(e) => {
+                eprintln!("Unable to read blocked songs from stdin: {}", e);
+                std::process::exit(EXIT_CODE_STDIN_ERROR);
+            }
         }
     }
+
     let blocked_songs = config::get_blocked_songs();
     if let Ok(songs) = &blocked_songs {
         debug!("{} songs are blocked.", songs.len());
     }
+    if mode::get_mode() == mode::Mode::Allowlist {
+        if let Err(e) = config::get_allowed_songs() {
+            warn!(
+                "Unable to read allowed songs: {}. No songs will be skipped until this is fixed.",
+                e
+            );
+        }
+    }
+    config_watcher::start();
+    summary::start();
 
+    let startup_delay = settings::get().startup_delay_secs;
+    if startup_delay > 0 {
+        info!(
+            "Waiting {} seconds before connecting to D-Bus.",
+            startup_delay
+        );
+        std::thread::sleep(std::time::Duration::from_secs(startup_delay));
+    }
     setup_mpris_connection();
 }
 
+fn check_config() -> i32 {
+    let settings_errors = match settings::error() {
+        Some(error) => {
+            println!("{}", error);
+            1
+        }
+        None => 0,
+    };
+    match config::check_blocked_songs() {
+        Ok(blocked_songs) => {
+            for error in blocked_songs.errors() {
+                println!("{}", error);
+            }
+            let errors = settings_errors + blocked_songs.errors().len();
+            println!("{} rules, {} errors.", blocked_songs.len(), errors);
+            if errors == 0 {
+                0
+            } else {
+                EXIT_CODE_CONFIG_ERROR
+            }
+        }
+        Err(e) => {
+            println!("Unable to read config files: {}", e);
+            EXIT_CODE_CONFIG_ERROR
+        }
+    }
+}
+
 pub const APPLICATION_NAME: &str = "audiowarden";
+
+pub const EXIT_CODE_CONFIG_ERROR: i32 = 1;
+pub const EXIT_CODE_INVALID_ARGUMENTS: i32 = 2;
+pub const EXIT_CODE_SOCKET_ERROR: i32 = 3;
+pub const EXIT_CODE_STDIN_ERROR: i32 = 4;
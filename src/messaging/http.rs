@@ -0,0 +1,204 @@
+use std::io::{BufRead, BufReader, Read, Write};
+use std::net::{Ipv4Addr, TcpListener, TcpStream};
+use std::sync::mpsc::Sender;
+use std::sync::Arc;
+use std::time::Duration;
+use std::{io, thread};
+
+use url::Url;
+
+use crate::error::AudioWardenError;
+use crate::json;
+use crate::messaging::{parse_client_message, send_and_await_response, ClientRequest};
+
+// Requests only consist of the request line and a few headers, so anything longer is rejected.
+const MAX_REQUEST_LENGTH: u64 = 8192;
+
+// Keeps idle or slow clients from occupying a thread forever.
+const CLIENT_TIMEOUT: Duration = Duration::from_secs(5);
+
+// Every request must carry this header. Browsers can't add custom headers to cross-origin requests
+// without a CORS preflight (which we never answer), so websites can't send commands to the API.
+const REQUIRED_HEADER: &str = "X-Audiowarden";
+
+struct HttpRequest {
+    method: String,
+    target: String,
+    headers: Vec<(String, String)>,
+}
+
+impl HttpRequest {
+    fn header(&self, name: &str) -> Option<&str> {
+        self.headers
+            .iter()
+            .find(|(key, _)| key.eq_ignore_ascii_case(name))
+            .map(|(_, value)| value.as_str())
+    }
+}
+
+pub fn open_and_listen_http(port: u16, tx: Sender<ClientRequest>) -> Result<(), AudioWardenError> {
+    // Only bound to localhost: The API is unauthenticated, so it must not be reachable from other
+    // machines.
+    let listener = TcpListener::bind((Ipv4Addr::LOCALHOST, port)).map_err(|e| {
+        AudioWardenError::GenericError(format!(
+            "Unable to bind to {}:{}: {}",
+            Ipv4Addr::LOCALHOST,
+            port,
+            e
+        ))
+    })?;
+    info!(
+        "HTTP API listening on http://{}:{}",
+        Ipv4Addr::LOCALHOST,
+        port
+    );
+
+    let tx = Arc::new(tx);
+    for stream in listener.incoming() {
+        match stream {
+            Ok(stream) => {
+                let tx = tx.clone();
+                thread::spawn(move || {
+                    handle_client(stream, tx);
+                });
+            }
+            Err(err) => {
+                error!("Error accepting connection on HTTP port: {}", err);
+            }
+        }
+    }
+
+    Ok(())
+}
+
+fn handle_client(mut stream: TcpStream, tx: Arc<Sender<ClientRequest>>) {
+    if let Err(e) = stream
+        .set_read_timeout(Some(CLIENT_TIMEOUT))
+        .and_then(|()| stream.set_write_timeout(Some(CLIENT_TIMEOUT)))
+    {
+        warn!("Unable to set timeout on HTTP connection: {:?}", e);
+        return;
+    }
+    let (status, response) = match read_request(&stream) {
+        Ok(request) => process_request(&request, &tx),
+        Err(e) => {
+            error!("Unable to read HTTP request: {:?}", e);
+            ("400 Bad Request", "error: invalid request\n".to_string())
+        }
+    };
+    let body = format!(
+        "{{\"ok\":{},\"response\":\"{}\"}}\n",
+        !response.starts_with("error: "),
+        json::escape(response.trim_end())
+    );
+    let http_response = format!(
+        "HTTP/1.1 {}\r\nContent-Type: application/json\r\nContent-Length: {}\r\n\
+            Connection: close\r\n\r\n{}",
+        status,
+        body.len(),
+        body
+    );
+    if let Err(e) = stream.write_all(http_response.as_bytes()) {
+        warn!("Unable to write HTTP response: {:?}", e);
+    }
+}
+
+// Commands are sent via POST to /<command>, with the optional argument in the query parameter
+// arg, e.g. /explain?arg=https://open.spotify.com/track/...
+fn process_request(request: &HttpRequest, tx: &Sender<ClientRequest>) -> (&'static str, String) {
+    // Websites must not be able to control audiowarden: Browsers send Origin and Sec-Fetch-* with
+    // (most) requests, and they can't send our custom header without a preflight. Checking the
+    // Host header protects against DNS rebinding.
+    let sent_by_browser = ["Origin", "Sec-Fetch-Site", "Sec-Fetch-Mode"]
+        .iter()
+        .any(|name| request.header(name).is_some());
+    if sent_by_browser
+        || request.header(REQUIRED_HEADER).is_none()
+        || !is_local_host(request.header("Host"))
+    {
+        return (
+            "403 Forbidden",
+            format!(
+                "error: only local, non-browser clients that send the {} header are allowed\n",
+                REQUIRED_HEADER
+            ),
+        );
+    }
+    // GET requests can be triggered by a lot of things (links, images, prefetching…), so they're
+    // not accepted, even for read-only commands.
+    if request.method != "POST" {
+        return (
+            "405 Method Not Allowed",
+            "error: only POST is supported\n".to_string(),
+        );
+    }
+    let url = match Url::parse("http://localhost").and_then(|base| base.join(&request.target)) {
+        Ok(url) => url,
+        Err(e) => return ("400 Bad Request", format!("error: invalid URL: {}\n", e)),
+    };
+    let command = url.path().trim_start_matches('/');
+    let argument = url
+        .query_pairs()
+        .find(|(key, _)| key == "arg")
+        .map(|(_, value)| value.into_owned())
+        .unwrap_or_default();
+    match parse_client_message(&format!("{} {}", command, argument)) {
+        Some(Ok(client_message)) => ("200 OK", send_and_await_response(client_message, tx)),
+        Some(Err(e)) => ("400 Bad Request", format!("error: {}\n", e)),
+        None => {
+            warn!("ClientMessage not recognized: {:?}", request.target);
+            (
+                "404 Not Found",
+                format!("error: unknown command '{}'; try 'help'\n", command),
+            )
+        }
+    }
+}
+
+fn is_local_host(host: Option<&str>) -> bool {
+    let host = match host {
+        Some(host) => host,
+        None => return false,
+    };
+    let hostname = host.rsplit_once(':').map_or(host, |(hostname, _)| hostname);
+    hostname == "localhost" || hostname == "127.0.0.1"
+}
+
+fn read_request<R>(stream: R) -> io::Result<HttpRequest>
+where
+    R: Read,
+{
+    let mut reader = BufReader::new(stream.take(MAX_REQUEST_LENGTH));
+    let mut request_line = String::new();
+    reader.read_line(&mut request_line)?;
+    let mut parts = request_line.split_whitespace();
+    let (method, target) = match (parts.next(), parts.next()) {
+        (Some(method), Some(target)) => (method.to_string(), target.to_string()),
+        _ => {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("invalid request line: {:?}", request_line),
+            ))
+        }
+    };
+    let mut headers = vec![];
+    loop {
+        let mut line = String::new();
+        if reader.read_line(&mut line)? == 0 {
+            break;
+        }
+        let line = line.trim_end();
+        if line.is_empty() {
+            // End of the headers. Requests don't need a body, so we don't read it.
+            break;
+        }
+        if let Some((key, value)) = line.split_once(':') {
+            headers.push((key.trim().to_string(), value.trim().to_string()));
+        }
+    }
+    Ok(HttpRequest {
+        method,
+        target,
+        headers,
+    })
+}
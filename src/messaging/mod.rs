@@ -1,66 +1,88 @@
-use crate::config::add_to_config_file;
-use crate::mpris;
-use std::sync::mpsc::{channel, Receiver, Sender};
+use crate::blocklist::RuleKind;
+use crate::config;
+use crate::config::add_blocked_song;
+use crate::mode::Mode;
+use crate::mpris::SongAttributes;
+use crate::{mode, mpris, settings, state, EXIT_CODE_SOCKET_ERROR};
+use std::sync::mpsc::{channel, Receiver, RecvTimeoutError, Sender};
+use std::time::Duration;
 
+mod http;
 mod socket;
 
 pub fn setup_channel() {
     std::thread::spawn(move || {
-        let (tx, rx): (Sender<ClientMessage>, Receiver<ClientMessage>) = channel();
+        let (tx, rx): (Sender<ClientRequest>, Receiver<ClientRequest>) = channel();
+        if let Some(port) = settings::get().http_api_port {
+            let tx = tx.clone();
+            std::thread::spawn(move || {
+                if let Err(err) = http::open_and_listen_http(port, tx) {
+                    error!(
+                        "Unable to start HTTP API: {}. audiowarden keeps running, but will \
+                            not respond to HTTP requests.",
+                        err
+                    );
+                }
+            });
+        }
         std::thread::spawn(|| {
             if let Err(err) = socket::open_and_listen_unix_socket(tx) {
-                error!("Unable to open unix socket: {:?}", err);
+                // Without the socket, commands like block_current_song silently stop working,
+                // which is hard to notice for users.
+                if settings::get().exit_on_socket_error {
+                    error!(
+                        "Unable to open unix socket: {}. Exiting, since audiowarden can't be \
+                            controlled without it. Set exit_on_socket_error = false to keep \
+                            running without the socket.",
+                        err
+                    );
+                    std::process::exit(EXIT_CODE_SOCKET_ERROR);
+                } else {
+                    error!(
+                        "Unable to open unix socket: {}. audiowarden keeps running, but will \
+                            not respond to any commands. Set exit_on_socket_error = true to \
+                            exit instead.",
+                        err
+                    );
+                }
             }
         });
         process_incoming_messages(rx);
     });
 }
 
-fn process_incoming_messages(rx: Receiver<ClientMessage>) {
+fn process_incoming_messages(rx: Receiver<ClientRequest>) {
     loop {
         match rx.recv() {
-            Ok(msg) => match msg {
-                ClientMessage::BlockCurrentSong => {
-                    match mpris::current_song() {
-                        None => {
-                            warn!(
-                                "Cannot block song, because we're unable to \
-                                    determine the current song."
-                            );
-                        }
-                        Some(song_attrs) => {
-                            info!("Received request to block song: {:?}", song_attrs);
-                            let attributes = vec![
-                                song_attrs
-                                    .artist
-                                    .map(|artist| format!("Artist: {}", artist)),
-                                song_attrs.title.map(|title| format!("Title: {}", title)),
-                            ];
-                            let attributes: Vec<&str> = attributes
-                                .iter()
-                                .filter_map(|x| x.as_ref())
-                                .map(|x| x.as_str())
-                                .collect();
-                            let comment = if attributes.is_empty() {
-                                None
-                            } else {
-                                Some(format!("# {}", attributes.join(", ")))
-                            };
-
-                            let prefix = match comment {
-                                Some(c) => format!("{}\n", c),
-                                None => "".to_string(),
-                            };
-
-                            let config_entry = format!("\n{}{}\n", prefix, song_attrs.url);
-                            if let Err(e) = add_to_config_file(&config_entry) {
-                                warn!("Unable to add entry to config file: {:?}", e);
-                            }
+            Ok(request) => {
+                let response = match &request.message {
+                    ClientMessage::BlockCurrentSong(list) => block_current_song(list.as_deref()),
+                    ClientMessage::TestSkip => test_skip(),
+                    ClientMessage::Explain(url) => explain(url),
+                    ClientMessage::Simulate(song) => simulate(song),
+                    ClientMessage::SetMode(new_mode) => set_mode(*new_mode),
+                    ClientMessage::GetMode => format!("{}\n", mode::get_mode()),
+                    ClientMessage::SetBlockingEnabled(enabled) => set_blocking_enabled(*enabled),
+                    ClientMessage::Status => status(),
+                    ClientMessage::Config => effective_config(),
+                    ClientMessage::Help => help(),
+                    ClientMessage::RecentEvents => {
+                        let events = mpris::recent_events();
+                        if events.is_empty() {
+                            "No songs received yet.\n".to_string()
+                        } else {
+                            events.iter().map(|e| format!("{}\n", e)).collect()
                         }
                     }
-                    mpris::play_next();
+                };
+                if let Err(e) = request.back_channel.send(response) {
+                    // The client has probably given up waiting for the response.
+                    warn!(
+                        "Unable to send response for message {:?} back to client: {:?}",
+                        request.message, e
+                    );
                 }
-            },
+            }
             Err(e) => {
                 error!("Error while receiving message on channel: {:?}", e);
                 // Avoid spamming the logs in an infinite loop:
@@ -70,7 +92,415 @@ fn process_incoming_messages(rx: Receiver<ClientMessage>) {
     }
 }
 
-#[derive(Debug, Copy, Clone)]
+fn block_current_song(list: Option<&str>) -> String {
+    let response = match mpris::current_or_last_song() {
+        None => {
+            warn!(
+                "Cannot block song, because we're unable to \
+                    determine the current song."
+            );
+            "error: unable to determine the current song\n".to_string()
+        }
+        Some((song_attrs, remembered)) => {
+            info!("Received request to block song: {:?}", song_attrs);
+            let attributes = [
+                song_attrs
+                    .artist
+                    .as_ref()
+                    .map(|artist| format!("Artist: {}", artist)),
+                song_attrs
+                    .title
+                    .as_ref()
+                    .map(|title| format!("Title: {}", title)),
+            ];
+            let attributes: Vec<&str> = attributes
+                .iter()
+                .filter_map(|x| x.as_ref())
+                .map(|x| x.as_str())
+                .collect();
+            let comment = if attributes.is_empty() {
+                None
+            } else {
+                Some(attributes.join(", "))
+            };
+
+            match add_blocked_song(&song_attrs.url, comment.as_deref(), list) {
+                Ok(()) if remembered => format!(
+                    "Blocked song: {} (the player couldn't be queried, so the song remembered \
+                        from before the restart was used)\n",
+                    song_attrs
+                ),
+                Ok(()) => format!("Blocked song: {}\n", song_attrs),
+                Err(e) => {
+                    warn!("Unable to add entry to config file: {:?}", e);
+                    format!("error: unable to add entry to config file: {}\n", e)
+                }
+            }
+        }
+    };
+    // Errors are already logged by play_next.
+    let _ = mpris::play_next();
+
+    response
+}
+
+// Only the URL is known, so rules that depend on other attributes of the song can't match. Use
+// simulate to check those as well.
+fn explain(url: &str) -> String {
+    let normalized_url = match config::normalize_url(url) {
+        Ok(normalized_url) => normalized_url,
+        Err(e) => return format!("error: not a valid URL: {}: {}\n", url, e),
+    };
+    let song = SongAttributes {
+        url: normalized_url,
+        raw_url: url.to_string(),
+        artist: None,
+        title: None,
+        length: None,
+        track_id: None,
+    };
+    let decision = match mpris::decide(&song) {
+        Ok(decision) => decision,
+        Err(e) => return format!("error: unable to determine blocked songs: {}\n", e),
+    };
+    let rule = match mode::get_mode() {
+        Mode::Off => None,
+        mode => match mpris::listed_songs(mode) {
+            Ok(listed_songs) => listed_songs
+                .find_match(&song)
+                .map(|rule| format!("{} ({})", rule.kind, rule.source)),
+            Err(e) => return format!("error: unable to determine blocked songs: {}\n", e),
+        },
+    };
+    format!(
+        "{} {}, matching rule: {}. Only URL rules were checked, use simulate to include artist \
+            and title.\n",
+        song.url,
+        decision,
+        rule.as_deref().unwrap_or("none")
+    )
+}
+
+// Runs the same decision logic as for songs received from the player, but never controls the
+// player. Stateful checks like loop detection are left out, so that simulating a song doesn't
+// affect how the next real song is handled.
+fn simulate(song: &SongAttributes) -> String {
+    info!("Received request to simulate song: {:?}", song);
+    match mpris::decide(song) {
+        Ok(decision) => format!("{} {}\n", song, decision),
+        Err(e) => format!("error: unable to determine blocked songs: {}\n", e),
+    }
+}
+
+// Parses "URL [ARTIST - TITLE]", e.g. "https://open.spotify.com/track/... Some Artist - Some Title".
+fn parse_simulated_song(argument: &str) -> Result<SongAttributes, String> {
+    let (url, rest) = argument.split_once(' ').unwrap_or((argument, ""));
+    if url.is_empty() {
+        return Err("simulate requires a URL".to_string());
+    }
+    let normalized_url =
+        config::normalize_url(url).map_err(|e| format!("not a valid URL: {}: {}", url, e))?;
+    let rest = rest.trim();
+    let (artist, title) = match rest.split_once(" - ") {
+        Some((artist, title)) => (artist.trim(), title.trim()),
+        None => (rest, ""),
+    };
+    let non_empty = |s: &str| Some(s.to_string()).filter(|s| !s.is_empty());
+    Ok(SongAttributes {
+        url: normalized_url,
+        raw_url: url.to_string(),
+        artist: non_empty(artist),
+        title: non_empty(title),
+        length: None,
+        track_id: None,
+    })
+}
+
+fn set_mode(new_mode: Mode) -> String {
+    info!("Received request to set mode to {}.", new_mode);
+    if new_mode == Mode::Allowlist {
+        // Otherwise, the mode would be switched, but no song could be evaluated.
+        if let Err(e) = config::get_allowed_songs() {
+            warn!("Not switching to allowlist mode: {}", e);
+            return format!("error: unable to read allowed songs: {}\n", e);
+        }
+    }
+    mode::set_mode(new_mode);
+    format!("Mode set to {}.\n", new_mode)
+}
+
+fn set_blocking_enabled(enabled: bool) -> String {
+    let description = if enabled { "enabled" } else { "disabled" };
+    info!("Received request to set blocking to {}.", description);
+    match state::update(|state| state.blocking_enabled = enabled) {
+        Ok(()) => format!("Blocking {}.\n", description),
+        Err(e) => {
+            error!("Unable to save state: {:?}", e);
+            format!(
+                "error: blocking {} until restart, but unable to save state: {}\n",
+                description, e
+            )
+        }
+    }
+}
+
+fn status() -> String {
+    let state = state::get();
+    let blocking = if state.blocking_enabled {
+        "enabled"
+    } else {
+        "disabled"
+    };
+    let (blocked_songs, config_errors) = match config::get_blocked_songs() {
+        Ok(blocked_songs) => (
+            blocked_songs.len().to_string(),
+            blocked_songs
+                .errors()
+                .iter()
+                .map(|e| format!("\n  {}", e))
+                .collect(),
+        ),
+        Err(e) => (format!("unknown ({})", e), "".to_string()),
+    };
+    format!(
+        "Blocking: {}\nMode: {}\nBlocked songs: {}\nSongs skipped: {}\nConfig errors:{}\n",
+        blocking,
+        mode::get_mode(),
+        blocked_songs,
+        state.songs_skipped,
+        if config_errors.is_empty() {
+            " none"
+        } else {
+            &config_errors
+        }
+    )
+}
+
+// Shows how the config files were interpreted, which is useful to check whether a setting took
+// effect.
+fn effective_config() -> String {
+    let rules = match config::get_blocked_songs() {
+        Ok(blocked_songs) => {
+            let (mut urls, mut durations, mut title_keywords, mut conditions) = (0, 0, 0, 0);
+            for rule in blocked_songs.rules() {
+                match rule.kind {
+                    RuleKind::Url(_) => urls += 1,
+                    RuleKind::MinDuration(_) | RuleKind::MaxDuration(_) => durations += 1,
+                    RuleKind::TitleKeyword(_) => title_keywords += 1,
+                    RuleKind::Conditions { .. } => conditions += 1,
+                }
+            }
+            format!(
+                "{} (URL: {}, duration: {}, title keyword: {}, conditions: {}), {} errors",
+                blocked_songs.len(),
+                urls,
+                durations,
+                title_keywords,
+                conditions,
+                blocked_songs.errors().len()
+            )
+        }
+        Err(e) => format!("unknown ({})", e),
+    };
+    let settings = match toml::to_string(settings::get()) {
+        Ok(settings) => settings,
+        Err(e) => return format!("error: unable to serialize settings: {}\n", e),
+    };
+    format!(
+        "Mode: {}\nRules: {}\n\n# Settings\n{}",
+        mode::get_mode(),
+        rules,
+        settings
+    )
+}
+
+fn test_skip() -> String {
+    // Useful to verify that audiowarden is able to control the player, independently of whether
+    // or not songs are matched correctly.
+    info!("Received request to test skipping to the next song.");
+    match mpris::play_next() {
+        Ok(()) => "Skipped to the next song.\n".to_string(),
+        Err(e) => format!("error: unable to skip to the next song: {}\n", e),
+    }
+}
+
+struct Command {
+    name: &'static str,
+    // Shown by the help command.
+    usage: &'static str,
+    description: &'static str,
+    // Creates the message from the (trimmed) argument.
+    parse: fn(&str) -> Result<ClientMessage, String>,
+}
+
+// All commands supported by the unix socket and the HTTP API.
+const COMMANDS: &[Command] = &[
+    Command {
+        name: "block_current_song",
+        usage: "block_current_song [LIST]",
+        description: "Block the current song, optionally in blocked_songs.conf.d/LIST.conf",
+        parse: |list| {
+            let list = Some(list.to_string()).filter(|list| !list.is_empty());
+            Ok(ClientMessage::BlockCurrentSong(list))
+        },
+    },
+    Command {
+        name: "test_skip",
+        usage: "test_skip",
+        description: "Skip to the next song to check whether the player can be controlled",
+        parse: |argument| without_argument(argument, ClientMessage::TestSkip),
+    },
+    Command {
+        name: "explain",
+        usage: "explain URL",
+        description: "Show whether the song with the given URL is blocked, and why",
+        parse: |url| {
+            if url.is_empty() {
+                Err("explain requires a URL".to_string())
+            } else {
+                Ok(ClientMessage::Explain(url.to_string()))
+            }
+        },
+    },
+    Command {
+        name: "simulate",
+        usage: "simulate URL [ARTIST - TITLE]",
+        description: "Show what would happen if the player started the given song",
+        parse: |argument| parse_simulated_song(argument).map(ClientMessage::Simulate),
+    },
+    Command {
+        name: "set_mode",
+        usage: "set_mode MODE",
+        description: "Switch to the given mode: blocklist, allowlist or off",
+        parse: |mode| mode.parse().map(ClientMessage::SetMode),
+    },
+    Command {
+        name: "get_mode",
+        usage: "get_mode",
+        description: "Show the current mode",
+        parse: |argument| without_argument(argument, ClientMessage::GetMode),
+    },
+    Command {
+        name: "enable",
+        usage: "enable",
+        description: "Enable skipping songs",
+        parse: |argument| without_argument(argument, ClientMessage::SetBlockingEnabled(true)),
+    },
+    Command {
+        name: "disable",
+        usage: "disable",
+        description: "Disable skipping songs until enabled again",
+        parse: |argument| without_argument(argument, ClientMessage::SetBlockingEnabled(false)),
+    },
+    Command {
+        name: "status",
+        usage: "status",
+        description: "Show whether blocking is enabled, the mode and config errors",
+        parse: |argument| without_argument(argument, ClientMessage::Status),
+    },
+    Command {
+        name: "config",
+        usage: "config",
+        description: "Show the effective configuration",
+        parse: |argument| without_argument(argument, ClientMessage::Config),
+    },
+    Command {
+        name: "recent_events",
+        usage: "recent_events",
+        description: "Show the songs most recently received from the player",
+        parse: |argument| without_argument(argument, ClientMessage::RecentEvents),
+    },
+    Command {
+        name: "help",
+        usage: "help",
+        description: "Show this list of commands",
+        parse: |argument| without_argument(argument, ClientMessage::Help),
+    },
+];
+
+fn without_argument(argument: &str, message: ClientMessage) -> Result<ClientMessage, String> {
+    if argument.is_empty() {
+        Ok(message)
+    } else {
+        Err(format!("unexpected argument '{}'", argument))
+    }
+}
+
+// Shared by the unix socket and the HTTP API. Returns None if the command is not recognized, and an
+// error if the command is recognized, but its argument is invalid.
+fn parse_client_message(message: &str) -> Option<Result<ClientMessage, String>> {
+    let message = message.trim();
+    let (name, argument) = message.split_once(' ').unwrap_or((message, ""));
+    let command = COMMANDS.iter().find(|command| command.name == name)?;
+    Some((command.parse)(argument.trim()))
+}
+
+fn help() -> String {
+    let width = COMMANDS
+        .iter()
+        .map(|command| command.usage.len())
+        .max()
+        .unwrap_or_default();
+    COMMANDS
+        .iter()
+        .map(|command| {
+            format!(
+                "{:width$}  {}\n",
+                command.usage,
+                command.description,
+                width = width
+            )
+        })
+        .collect()
+}
+
+fn send_and_await_response(message: ClientMessage, tx: &Sender<ClientRequest>) -> String {
+    let (back_channel, rx_response) = channel();
+    let request = ClientRequest {
+        message: message.clone(),
+        back_channel,
+    };
+    if let Err(e) = tx.send(request) {
+        warn!("Unable to send message {:?}: {:?}", message, e);
+        return "error: audiowarden is unable to process messages\n".to_string();
+    }
+
+    let timeout = Duration::from_millis(settings::get().socket_response_timeout_ms);
+    match rx_response.recv_timeout(timeout) {
+        Ok(response) => response,
+        Err(RecvTimeoutError::Timeout) => {
+            warn!(
+                "No response for message {:?} within {:?}, giving up.",
+                message, timeout
+            );
+            "error: timed out waiting for audiowarden to process the message\n".to_string()
+        }
+        Err(RecvTimeoutError::Disconnected) => {
+            warn!("No response for message {:?}: channel closed.", message);
+            "error: audiowarden was unable to process the message\n".to_string()
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
 pub enum ClientMessage {
-    BlockCurrentSong,
+    // Optionally contains the name of the list in blocked_songs.conf.d to add the song to.
+    BlockCurrentSong(Option<String>),
+    TestSkip,
+    Explain(String),
+    Simulate(SongAttributes),
+    SetMode(Mode),
+    GetMode,
+    SetBlockingEnabled(bool),
+    Status,
+    Config,
+    RecentEvents,
+    Help,
+}
+
+#[derive(Debug)]
+pub struct ClientRequest {
+    pub message: ClientMessage,
+    // Used to send the response back to the client connected to the unix socket.
+    pub back_channel: Sender<String>,
 }
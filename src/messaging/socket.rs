@@ -1,5 +1,5 @@
 use std::io::ErrorKind::NotFound;
-use std::io::{ErrorKind, Read};
+use std::io::{BufRead, BufReader, ErrorKind, Read, Write};
 use std::os::unix::net::{UnixListener, UnixStream};
 use std::path::{Path, PathBuf};
 use std::sync::mpsc::Sender;
@@ -7,19 +7,45 @@ use std::sync::Arc;
 use std::{env, fs, io, thread};
 
 use crate::error::AudioWardenError;
-use crate::messaging::ClientMessage;
-use crate::APPLICATION_NAME;
+use crate::messaging::{parse_client_message, send_and_await_response, ClientRequest};
+use crate::{profile, APPLICATION_NAME};
 
-pub fn open_and_listen_unix_socket(tx: Sender<ClientMessage>) -> Result<(), AudioWardenError> {
+// All commands are short, so anything longer than this is not a valid command anyway.
+const MAX_MESSAGE_LENGTH: u64 = 4096;
+
+// Removes the socket file when the listener is dropped, e.g. if the thread panics, so that the file
+// usually doesn't exist anymore when audiowarden starts the next time.
+struct SocketGuard {
+    listener: UnixListener,
+    path: PathBuf,
+}
+
+impl Drop for SocketGuard {
+    fn drop(&mut self) {
+        if let Err(e) = remove_socketfile(&self.path) {
+            warn!(
+                "Unable to remove socket file {}: {}",
+                self.path.display(),
+                e
+            );
+        }
+    }
+}
+
+pub fn open_and_listen_unix_socket(tx: Sender<ClientRequest>) -> Result<(), AudioWardenError> {
     let path = get_and_create_socket_path()?;
     let path = path.join("audiowarden.sock");
     // If the socket file already exists, just remove it. If we open the existing file, we get
     // the error message "Address already in use".
     remove_socketfile(&path)?;
-    let listener = UnixListener::bind(&path)?;
+    let listener = UnixListener::bind(&path).map_err(|e| {
+        AudioWardenError::GenericError(format!("Unable to bind to {}: {}", path.display(), e))
+    })?;
+    info!("Listening on unix socket {}", path.display());
+    let socket = SocketGuard { listener, path };
 
     let tx = Arc::new(tx);
-    for stream in listener.incoming() {
+    for stream in socket.listener.incoming() {
         match stream {
             Ok(stream) => {
                 let tx = tx.clone();
@@ -37,7 +63,7 @@ pub fn open_and_listen_unix_socket(tx: Sender<ClientMessage>) -> Result<(), Audi
 }
 
 fn get_and_create_socket_path() -> Result<PathBuf, AudioWardenError> {
-    let path = get_socket_path()?;
+    let path = profile::namespaced_path(&get_socket_path()?);
     let result = match fs::create_dir_all(&path) {
         Ok(()) => Ok(()),
         Err(e) if e.kind() == ErrorKind::AlreadyExists => {
@@ -83,29 +109,39 @@ pub fn remove_socketfile(path: &Path) -> io::Result<()> {
     }
 }
 
-pub fn handle_client(mut stream: UnixStream, tx: Arc<Sender<ClientMessage>>) {
-    let message_result = read_string(&mut stream);
-    match message_result {
-        Ok(s) if s == "block_current_song\n" || s == "block_current_song" => {
-            let message = ClientMessage::BlockCurrentSong;
-            if let Err(e) = tx.send(message) {
-                warn!("Unable to send message {:?}: {:?}", message, e);
-            }
-        }
-        Ok(s) => {
-            warn!("ClientMessage not recognized: {}", s);
-        }
+pub fn handle_client(mut stream: UnixStream, tx: Arc<Sender<ClientRequest>>) {
+    let message = match read_line(&stream) {
+        Ok(message) => message,
         Err(e) => {
             error!("Unable to read message from socket: {:?}", e);
+            return;
         }
     };
+    let response = match parse_client_message(&message) {
+        Some(Ok(client_message)) => send_and_await_response(client_message, &tx),
+        Some(Err(e)) => format!("error: {}\n", e),
+        None => {
+            warn!("ClientMessage not recognized: {:?}", message.trim());
+            let command = message.split_whitespace().next().unwrap_or_default();
+            format!("error: unknown command '{}'; try 'help'\n", command)
+        }
+    };
+
+    if let Err(e) = stream.write_all(response.as_bytes()) {
+        warn!("Unable to write response to socket: {:?}", e);
+    }
 }
 
-fn read_string<R>(stream: &mut R) -> io::Result<String>
+// Only reads a single line instead of reading until EOF: This way, clients can wait for the
+// response without having to close their write half of the connection first.
+fn read_line<R>(stream: R) -> io::Result<String>
 where
     R: Read,
 {
-    let mut buffer = String::new();
-    stream.read_to_string(&mut buffer)?;
-    Ok(buffer)
+    let mut reader = BufReader::new(stream.take(MAX_MESSAGE_LENGTH));
+    let mut buffer = Vec::new();
+    reader.read_until(b'\n', &mut buffer)?;
+    // Invalid UTF-8 can't be a valid command anyway, so there's no need to fail here: Decoding it
+    // lossily lets us reject it just like any other unrecognized command.
+    Ok(String::from_utf8_lossy(&buffer).into_owned())
 }
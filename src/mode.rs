@@ -0,0 +1,89 @@
+use std::fmt::{Display, Formatter};
+use std::str::FromStr;
+use std::sync::Mutex;
+
+use serde::{Deserialize, Serialize};
+
+use crate::settings;
+
+// The mode can be changed at runtime via the unix socket. Until then, the mode from the settings
+// is used.
+static CURRENT_MODE: Mutex<Option<Mode>> = Mutex::new(None);
+
+// Counts the track changes since allowlist mode was enabled, for the allowlist_warmup setting.
+static ALLOWLIST_WARMUP: Mutex<Warmup> = Mutex::new(Warmup {
+    track_changes: 0,
+    last_url: None,
+});
+
+struct Warmup {
+    track_changes: usize,
+    // Players usually emit multiple signals for the same song, which shouldn't count as multiple
+    // track changes.
+    last_url: Option<String>,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Mode {
+    // Skip all songs that are blocked.
+    Blocklist,
+    // Skip all songs except for those that are allowed.
+    Allowlist,
+    // Don't skip any songs.
+    Off,
+}
+
+pub fn get_mode() -> Mode {
+    CURRENT_MODE
+        .lock()
+        .unwrap()
+        .unwrap_or_else(|| settings::get().mode)
+}
+
+pub fn set_mode(mode: Mode) {
+    *CURRENT_MODE.lock().unwrap() = Some(mode);
+    if mode == Mode::Allowlist {
+        let mut warmup = ALLOWLIST_WARMUP.lock().unwrap();
+        warmup.track_changes = 0;
+        warmup.last_url = None;
+    }
+}
+
+// Returns true if the given song is one of the first allowlist_warmup songs since allowlist mode
+// was enabled, which should be allowed through to avoid abruptly skipping everything.
+pub fn in_allowlist_warmup(url: &str) -> bool {
+    let mut warmup = ALLOWLIST_WARMUP.lock().unwrap();
+    if warmup.last_url.as_deref() != Some(url) {
+        warmup.track_changes += 1;
+        warmup.last_url = Some(url.to_string());
+    }
+    warmup.track_changes <= settings::get().allowlist_warmup
+}
+
+impl FromStr for Mode {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "blocklist" => Ok(Mode::Blocklist),
+            "allowlist" => Ok(Mode::Allowlist),
+            "off" => Ok(Mode::Off),
+            other => Err(format!(
+                "unknown mode '{}', expected one of: blocklist, allowlist, off",
+                other
+            )),
+        }
+    }
+}
+
+impl Display for Mode {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        let mode = match self {
+            Mode::Blocklist => "blocklist",
+            Mode::Allowlist => "allowlist",
+            Mode::Off => "off",
+        };
+        write!(f, "{}", mode)
+    }
+}
@@ -1,6 +1,8 @@
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap, VecDeque};
 use std::fmt::{Display, Formatter};
-use std::time::Duration;
+use std::io;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
 
 use dbus::arg::messageitem::{MessageItem, MessageItemDict};
 use dbus::arg::RefArg;
@@ -10,8 +12,22 @@ use dbus::channel::MatchingReceiver;
 use dbus::message::MatchRule;
 use dbus::strings::Member;
 use dbus::{arg, MessageType};
+use serde::{Deserialize, Serialize};
+use url::Url;
 
-use crate::config;
+use crate::blocklist::{BlockAction, BlockList};
+use crate::mode::Mode;
+use crate::settings::Settings;
+use crate::{
+    config, mode, one_chance, quiet_hours, settings, skip_history, state, status_file, summary,
+};
+
+// The most recently received songs, so that users can include them in bug reports without having
+// to enable debug logging first.
+static RECENT_EVENTS: Mutex<VecDeque<(Instant, SongAttributes)>> = Mutex::new(VecDeque::new());
+
+// The URL of the song we've most recently received a signal for since audiowarden was started.
+static LAST_RECEIVED_URL: Mutex<Option<String>> = Mutex::new(None);
 
 pub fn setup_mpris_connection() {
     let conn = Connection::new_session().expect("Unable to open D-Bus connection.");
@@ -35,9 +51,13 @@ pub fn setup_mpris_connection() {
     );
     result.expect("Unable to execute method against D-Bus.");
 
+    let mut watchdog = Watchdog::new();
+    let last_message_received = watchdog.last_message_received.clone();
     conn.start_receive(
         rule,
-        Box::new(|msg, _| {
+        Box::new(move |msg, _| {
+            *last_message_received.lock().unwrap() = Instant::now();
+            // The returned decisions have already been logged, so there is nothing left to do.
             handle_message(&msg);
             true
         }),
@@ -46,14 +66,105 @@ pub fn setup_mpris_connection() {
     loop {
         conn.process(Duration::from_millis(1000))
             .expect("Unable to process D-Bus message.");
+        watchdog.check();
+        one_chance::check_position();
+    }
+}
+
+// Warns if we haven't received any MPRIS signals for a long time even though the player has moved
+// on to another song: This usually means that our match rule no longer works (e.g. because Spotify
+// has changed its D-Bus behavior), in which case audiowarden silently stops blocking songs.
+struct Watchdog {
+    last_message_received: Arc<Mutex<Instant>>,
+    last_check: Instant,
+}
+
+impl Watchdog {
+    fn new() -> Self {
+        let now = Instant::now();
+        Watchdog {
+            last_message_received: Arc::new(Mutex::new(now)),
+            last_check: now,
+        }
+    }
+
+    fn check(&mut self) {
+        let timeout = Duration::from_secs(settings::get().watchdog_timeout_secs);
+        if timeout.is_zero() || self.last_check.elapsed() < timeout {
+            return;
+        }
+        self.last_check = Instant::now();
+        let elapsed = self.last_message_received.lock().unwrap().elapsed();
+        if elapsed < timeout || !player_is_playing() {
+            return;
+        }
+        // Spotify doesn't send any signals while a song is playing, so long songs or podcast
+        // episodes are fine, as long as we've received a signal for the current song.
+        let current_url = match current_song() {
+            Some(song) => song.url,
+            None => return,
+        };
+        if LAST_RECEIVED_URL.lock().unwrap().as_ref() != Some(&current_url) {
+            warn!(
+                "No MPRIS signals received for {} seconds although Spotify is playing {}: \
+                    audiowarden is probably unable to detect songs.",
+                elapsed.as_secs(),
+                current_url
+            );
+        }
+    }
+}
+
+fn player_is_playing() -> bool {
+    // We can't use the connection that receives the MPRIS signals, because connections that
+    // have become a monitor are not allowed to send messages.
+    let conn = match Connection::new_session() {
+        Ok(conn) => conn,
+        Err(e) => {
+            warn!("Unable to open D-Bus connection: {:?}", e);
+            return false;
+        }
+    };
+    let proxy = conn.with_proxy(
+        "org.mpris.MediaPlayer2.spotify",
+        "/org/mpris/MediaPlayer2",
+        Duration::from_millis(5000),
+    );
+    let status: Result<String, dbus::Error> =
+        proxy.get("org.mpris.MediaPlayer2.Player", "PlaybackStatus");
+    // An error usually just means that Spotify is not running.
+    matches!(status.as_deref(), Ok("Playing"))
+}
+
+// The playback position within the current song, or None if it can't be determined.
+pub fn player_position() -> Option<Duration> {
+    let conn = match Connection::new_session() {
+        Ok(conn) => conn,
+        Err(e) => {
+            warn!("Unable to open D-Bus connection: {:?}", e);
+            return None;
+        }
+    };
+    let proxy = conn.with_proxy(
+        "org.mpris.MediaPlayer2.spotify",
+        "/org/mpris/MediaPlayer2",
+        Duration::from_millis(5000),
+    );
+    let position: Result<i64, dbus::Error> = proxy.get("org.mpris.MediaPlayer2.Player", "Position");
+    match position {
+        // MPRIS positions are in microseconds.
+        Ok(position) => u64::try_from(position).ok().map(Duration::from_micros),
+        Err(e) => {
+            debug!("Unable to fetch playback position: {:?}", e);
+            None
+        }
     }
 }
 
-pub fn play_next() {
+pub fn play_next() -> Result<(), dbus::Error> {
     // TODO it would be nice if we could just re-use an existing connection here instead of
     //   creating a new one, but Rust's ownership semantics makes this a bit difficult.
-    let conn =
-        Connection::new_session().expect("Unable to open D-Bus connection to play next song.");
+    let conn = Connection::new_session()?;
     let proxy = conn.with_proxy(
         "org.mpris.MediaPlayer2.spotify",
         "/org/mpris/MediaPlayer2",
@@ -62,65 +173,487 @@ pub fn play_next() {
 
     let result: Result<(), dbus::Error> =
         proxy.method_call("org.mpris.MediaPlayer2.Player", "Next", ());
-    if let Err(e) = result {
+    if let Err(e) = &result {
         error!(
             "Unable to execute method against D-Bus to play next song: {:?}",
             e
         );
     }
+
+    result
 }
 
-fn handle_message(message: &dbus::Message) {
-    match config::get_blocked_songs() {
-        Ok(blocked_songs) => {
-            debug!("{} songs are blocked.", blocked_songs.len());
-            for message_item in message.get_items() {
-                if let MessageItem::Dict(d) = &message_item {
-                    if let Some(attrs) = get_attrs(d) {
-                        let song_is_blocked = blocked_songs.contains(&attrs.url.to_string());
-                        let suffix = if song_is_blocked {
-                            play_next();
-                            "[BLOCKED]"
-                        } else {
-                            "[NOT BLOCKED]"
-                        };
-                        info!("{} {}", attrs, suffix);
-                    }
+fn handle_message(message: &dbus::Message) -> Vec<Decision> {
+    if !contains_metadata(message) {
+        // Spotify frequently sends signals that only contain e.g. the position, which we don't
+        // need to look at any further.
+        return vec![];
+    }
+    if !sender_is_trusted(message) {
+        debug!(
+            "Ignoring message from untrusted sender {:?}",
+            message.sender()
+        );
+        return vec![];
+    }
+
+    let mut decisions = vec![];
+    for song in song_attributes_from_message(message) {
+        // All songs need to be recorded, so that we can tell when a song comes up again, and
+        // so that multiple signals for the same song are only counted once.
+        let encounter = skip_history::record_encounter(&song.url);
+        if encounter.is_new {
+            summary::song_seen();
+        }
+        let mut decision = match decide(&song) {
+            Ok(decision) => decision,
+            Err(e) => {
+                error!("Unable to determine whether song is blocked: {:?}", e);
+                summary::error_occurred();
+                continue;
+            }
+        };
+        // Allowed songs also count as track changes for the warmup.
+        let in_warmup = matches!(decision, Decision::Allowed | Decision::NotAllowed)
+            && mode::in_allowlist_warmup(&song.url);
+        if in_warmup && decision == Decision::NotAllowed {
+            decision = Decision::Warmup;
+        }
+        let first_encounter = settings::get().skip_on_repeat && encounter.count == 1;
+        if first_encounter && matches!(decision, Decision::Blocked(_)) {
+            decision = Decision::FirstEncounter;
+        }
+        if let Some(action) = decision.action() {
+            if skip_history::allow_after_repeated_skips(&song.url) {
+                decision = Decision::AllowedAfterRepeatedSkips;
+            } else if skip_history::record_skip_unless_looping(&song.url) {
+                if apply_action(&song, action) && skip_history::is_first_block(&song.url) {
+                    summary::song_blocked();
                 }
+            } else {
+                decision = Decision::LoopDetected;
+            }
+        }
+        if decision.action() != Some(BlockAction::Mute) {
+            // The player was muted for the previous song only.
+            unmute();
+        }
+        one_chance::song_started(&song, decision);
+        info!(
+            url = song.url.as_str(),
+            artist = song.artist.as_deref(),
+            title = song.title.as_deref(),
+            decision:% = decision;
+            "{} {}", song, decision
+        );
+        status_file::update_status_file(&song, decision);
+        decisions.push(decision);
+    }
+
+    decisions
+}
+
+// Everything that decide_with depends on besides the song and the listed songs, so that decisions
+// can be made without the global state (e.g. in tests).
+struct DecisionContext<'a> {
+    settings: &'a Settings,
+    blocking_enabled: bool,
+    quiet: bool,
+    mode: Mode,
+    one_chance_songs: &'a BTreeMap<String, bool>,
+}
+
+// Determines what to do with the given song, without actually doing it.
+pub fn decide(song: &SongAttributes) -> io::Result<Decision> {
+    let state = state::get();
+    let context = DecisionContext {
+        settings: settings::get(),
+        blocking_enabled: state.blocking_enabled,
+        quiet: quiet_hours::is_quiet_now(),
+        mode: mode::get_mode(),
+        one_chance_songs: &state.one_chance_songs,
+    };
+    decide_with(song, &context, listed_songs)
+}
+
+// The songs that decide matches against in the given mode.
+pub fn listed_songs(mode: Mode) -> io::Result<Arc<BlockList>> {
+    match mode {
+        Mode::Allowlist => Ok(Arc::new(config::get_allowed_songs()?)),
+        _ => config::get_blocked_songs(),
+    }
+}
+
+// The listed songs are only loaded if they're needed, since reading the config files is slow.
+fn decide_with<F>(
+    song: &SongAttributes,
+    context: &DecisionContext,
+    load_listed_songs: F,
+) -> io::Result<Decision>
+where
+    F: FnOnce(Mode) -> io::Result<Arc<BlockList>>,
+{
+    if !context.blocking_enabled {
+        return Ok(Decision::Disabled);
+    }
+    if context.quiet {
+        return Ok(Decision::Quiet);
+    }
+    if !content_type_is_considered(&song.url, context.settings) {
+        return Ok(Decision::IgnoredContentType);
+    }
+    let mode = context.mode;
+    if mode == Mode::Off {
+        return Ok(Decision::Off);
+    }
+    let listed_songs = load_listed_songs(mode)?;
+    debug!("{} songs are listed in mode {}.", listed_songs.len(), mode);
+    let matching_rule = listed_songs.find_match(song);
+    let decision = match (mode, matching_rule) {
+        (Mode::Allowlist, Some(_)) => Decision::Allowed,
+        (Mode::Allowlist, None) => Decision::NotAllowed,
+        (_, Some(rule)) => {
+            let action = rule.action.unwrap_or(context.settings.block_action);
+            if rule.give_one_chance {
+                one_chance::decide(&song.url, action, context.one_chance_songs)
+            } else {
+                Decision::Blocked(action)
             }
         }
+        (_, None) if context.settings.skip_unknown_metadata && has_unknown_metadata(song) => {
+            Decision::UnknownMetadata
+        }
+        (_, None) => Decision::NotBlocked,
+    };
+
+    Ok(decision)
+}
+
+fn contains_metadata(message: &dbus::Message) -> bool {
+    message
+        .get_items()
+        .iter()
+        .any(|message_item| match message_item {
+            MessageItem::Dict(d) => d
+                .iter()
+                .any(|(key, _)| matches!(key, MessageItem::Str(s) if s == "Metadata")),
+            _ => false,
+        })
+}
+
+// Allows users to e.g. never skip podcast episodes, regardless of the rules.
+fn content_type_is_considered(url: &str, settings: &Settings) -> bool {
+    if url.contains("open.spotify.com/track/") {
+        settings.consider_tracks
+    } else if url.contains("open.spotify.com/episode/") {
+        settings.consider_episodes
+    } else {
+        true
+    }
+}
+
+// Usually ads or glitches, which would otherwise slip through artist/title-based rules.
+fn has_unknown_metadata(song: &SongAttributes) -> bool {
+    let is_unknown = |attribute: &Option<String>| attribute.as_ref().is_none_or(|a| a.is_empty());
+    is_unknown(&song.artist) && is_unknown(&song.title)
+}
+
+// Any local program can pretend to be Spotify by emitting signals with Spotify URLs, which would
+// cause us to skip songs in the real Spotify client. If trusted players are configured, we
+// therefore ignore signals from all other senders.
+fn sender_is_trusted(message: &dbus::Message) -> bool {
+    let trusted_players = &settings::get().trusted_players;
+    if trusted_players.is_empty() {
+        return true;
+    }
+    sender_owns_any_name(message, trusted_players)
+}
+
+fn sender_owns_any_name<S>(message: &dbus::Message, names: &[S]) -> bool
+where
+    S: AsRef<str>,
+{
+    let sender = match message.sender() {
+        Some(sender) => sender.to_string(),
+        None => return false,
+    };
+    // The sender is always a unique name like ":1.42", so we need to look up which unique names
+    // the trusted (well-known) names currently belong to.
+    let conn = match Connection::new_session() {
+        Ok(conn) => conn,
         Err(e) => {
-            error!("Unable to determine blocked songs: {:?}", e)
+            warn!("Unable to open D-Bus connection: {:?}", e);
+            return false;
+        }
+    };
+    let proxy = conn.with_proxy(
+        "org.freedesktop.DBus",
+        "/org/freedesktop/DBus",
+        Duration::from_millis(5000),
+    );
+    names.iter().any(|name| {
+        let name = name.as_ref();
+        if name == sender {
+            return true;
+        }
+        let owner: Result<(String,), dbus::Error> =
+            proxy.method_call("org.freedesktop.DBus", "GetNameOwner", (name,));
+        matches!(owner, Ok((owner,)) if owner == sender)
+    })
+}
+
+// The volume before the player was muted, so that it can be restored once the next song starts.
+static VOLUME_BEFORE_MUTE: Mutex<Option<f64>> = Mutex::new(None);
+
+// Returns true if the action was applied successfully.
+fn apply_action(song: &SongAttributes, action: BlockAction) -> bool {
+    // By the time we get here, the player may have already moved on to the next song (e.g.
+    // because the user skipped it manually), in which case we would skip an innocent song.
+    if let Some(current) = current_song() {
+        if current.url != song.url {
+            info!(
+                "Not applying action {}, because the current song has already changed: {}",
+                action, current
+            );
+            return false;
+        }
+    }
+    let result = match action {
+        BlockAction::Skip => {
+            // Errors are already logged by play_next.
+            let result = play_next();
+            if result.is_ok() {
+                let result = state::update(|state| state.songs_skipped += 1);
+                if let Err(e) = result {
+                    error!("Unable to save state: {}", e);
+                }
+            }
+            result
         }
+        BlockAction::Pause => pause().inspect_err(|e| {
+            error!("Unable to pause the player: {:?}", e);
+        }),
+        BlockAction::Mute => mute().inspect_err(|e| {
+            error!("Unable to mute the player: {:?}", e);
+        }),
+    };
+    if result.is_err() {
+        summary::error_occurred();
+    }
+    result.is_ok()
+}
+
+fn pause() -> Result<(), dbus::Error> {
+    let conn = Connection::new_session()?;
+    let proxy = conn.with_proxy(
+        "org.mpris.MediaPlayer2.spotify",
+        "/org/mpris/MediaPlayer2",
+        Duration::from_millis(5000),
+    );
+    proxy.method_call("org.mpris.MediaPlayer2.Player", "Pause", ())
+}
+
+fn mute() -> Result<(), dbus::Error> {
+    let conn = Connection::new_session()?;
+    let proxy = conn.with_proxy(
+        "org.mpris.MediaPlayer2.spotify",
+        "/org/mpris/MediaPlayer2",
+        Duration::from_millis(5000),
+    );
+    let mut volume_before_mute = VOLUME_BEFORE_MUTE.lock().unwrap();
+    if volume_before_mute.is_none() {
+        let volume: f64 = proxy.get("org.mpris.MediaPlayer2.Player", "Volume")?;
+        *volume_before_mute = Some(volume);
+    }
+    proxy.set("org.mpris.MediaPlayer2.Player", "Volume", 0.0)
+}
+
+fn unmute() {
+    let volume = match VOLUME_BEFORE_MUTE.lock().unwrap().take() {
+        Some(volume) => volume,
+        None => return,
+    };
+    let result = Connection::new_session().and_then(|conn| {
+        let proxy = conn.with_proxy(
+            "org.mpris.MediaPlayer2.spotify",
+            "/org/mpris/MediaPlayer2",
+            Duration::from_millis(5000),
+        );
+        proxy.set("org.mpris.MediaPlayer2.Player", "Volume", volume)
+    });
+    if let Err(e) = result {
+        error!("Unable to restore the volume to {}: {:?}", volume, e);
+    }
+}
+
+fn song_attributes_from_message(message: &dbus::Message) -> Vec<SongAttributes> {
+    let songs: Vec<SongAttributes> = message
+        .get_items()
+        .iter()
+        .filter_map(|message_item| match message_item {
+            MessageItem::Dict(d) => get_attrs(d, || {
+                sender_owns_any_name(message, &["org.mpris.MediaPlayer2.spotify"])
+            }),
+            _ => None,
+        })
+        .collect();
+    if songs.len() > 1 {
+        // Depends on the player's signal behavior, so this is not an error: All songs are
+        // evaluated.
+        debug!("Message contains {} songs.", songs.len());
+    }
+    record_recent_events(&songs);
+    if let Some(song) = songs.last() {
+        *LAST_RECEIVED_URL.lock().unwrap() = Some(song.url.clone());
+        remember_last_song(song);
+    }
+    songs
+}
+
+// Persisted, so that the song can still be blocked after a restart, before the player has sent
+// any new signals.
+fn remember_last_song(song: &SongAttributes) {
+    let last_url = state::get().last_song.map(|last_song| last_song.url);
+    if last_url.as_deref() == Some(song.url.as_str()) {
+        return;
+    }
+    if let Err(e) = state::update(|state| state.last_song = Some(song.clone())) {
+        error!("Unable to save state: {}", e);
+    }
+}
+
+// Right after a restart, the player may not be able to tell us the current song yet, in which case
+// we fall back to the last song we've received a signal for before the restart. Returns true if
+// that song was used. Once a signal has been received, the remembered song may be outdated (e.g.
+// the player was closed hours ago), so it's not used anymore.
+pub fn current_or_last_song() -> Option<(SongAttributes, bool)> {
+    if let Some(song) = current_song() {
+        return Some((song, false));
+    }
+    if LAST_RECEIVED_URL.lock().unwrap().is_some() {
+        return None;
+    }
+    let last_song = state::get().last_song;
+    if let Some(song) = &last_song {
+        info!(
+            "Using the song remembered from before the restart instead: {}",
+            song
+        );
+    }
+    last_song.map(|song| (song, true))
+}
+
+fn record_recent_events(songs: &[SongAttributes]) {
+    let capacity = settings::get().recent_events_capacity;
+    let mut recent_events = RECENT_EVENTS.lock().unwrap();
+    for song in songs {
+        recent_events.push_back((Instant::now(), song.clone()));
+    }
+    while recent_events.len() > capacity {
+        recent_events.pop_front();
     }
 }
 
+pub fn recent_events() -> Vec<String> {
+    RECENT_EVENTS
+        .lock()
+        .unwrap()
+        .iter()
+        .map(|(received, song)| {
+            format!(
+                "[{}s ago] {}, raw URL: {}",
+                received.elapsed().as_secs(),
+                song,
+                song.raw_url
+            )
+        })
+        .collect()
+}
+
 pub fn current_song() -> Option<SongAttributes> {
     // TODO it would be nice if we could just re-use an existing connection here instead of
     //   creating a new one, but Rust's ownership semantics makes this a bit difficult.
-    let conn =
-        Connection::new_session().expect("Unable to open D-Bus connection to play next song.");
+    let conn = match Connection::new_session() {
+        Ok(conn) => conn,
+        Err(e) => {
+            error!(
+                "Unable to open D-Bus connection to fetch current song: {:?}",
+                e
+            );
+            return None;
+        }
+    };
 
     let proxy = conn.with_proxy(
         "org.mpris.MediaPlayer2.spotify",
         "/org/mpris/MediaPlayer2",
         Duration::from_millis(5000),
     );
-    let metadata: HashMap<String, arg::Variant<Box<dyn RefArg>>> = proxy
-        .get("org.mpris.MediaPlayer2.Player", "Metadata")
-        .unwrap();
-    let title = &metadata["xesam:title"].as_str();
-    let url_attr = &metadata["xesam:url"].as_str();
+    let metadata: HashMap<String, arg::Variant<Box<dyn RefArg>>> =
+        match proxy.get("org.mpris.MediaPlayer2.Player", "Metadata") {
+            Ok(metadata) => metadata,
+            Err(e) => {
+                // This usually just means that Spotify is not running.
+                warn!("Unable to fetch metadata of current song: {:?}", e);
+                return None;
+            }
+        };
+    let title = metadata.get("xesam:title").and_then(|t| t.as_str());
+    let url_attr = settings::get()
+        .url_metadata_keys
+        .iter()
+        .find_map(|key| metadata.get(key).and_then(|u| u.as_str()));
     let artists: Option<&Vec<String>> = arg::prop_cast(&metadata, "xesam:artist");
-    let artist = artists.map(|a| a.join(", "));
+    let artist = match artists {
+        Some(a) => Some(a.join(", ")),
+        // Some players send a single string instead of a list of strings.
+        None => metadata
+            .get("xesam:artist")
+            .and_then(|a| a.as_str())
+            .map(|a| a.to_string()),
+    };
+
+    let length = metadata
+        .get("mpris:length")
+        .and_then(|l| l.as_i64().or_else(|| l.as_u64().map(|l| l as i64)))
+        .and_then(length_from_micros);
+
+    let track_id = metadata
+        .get("mpris:trackid")
+        .and_then(|t| t.as_str())
+        .and_then(track_id_from_trackid);
 
     url_attr.map(|url| SongAttributes {
-        url: url.to_string(),
+        url: config::normalize_url(url).unwrap_or_else(|_| url.to_string()),
+        raw_url: url.to_string(),
         artist,
         title: title.map(|x| x.to_string()),
+        length,
+        track_id,
     })
 }
 
+// The official Spotify client uses either "spotify:track:ID" or "/com/spotify/track/ID".
+fn track_id_from_trackid(trackid: &str) -> Option<String> {
+    trackid
+        .strip_prefix("spotify:track:")
+        .or_else(|| trackid.strip_prefix("/com/spotify/track/"))
+        .filter(|id| !id.is_empty() && id.chars().all(|c| c.is_ascii_alphanumeric()))
+        .map(|id| id.to_string())
+}
+
+fn length_from_micros(micros: i64) -> Option<Duration> {
+    u64::try_from(micros).ok().map(Duration::from_micros)
+}
+
+fn int_from_message_item(message_item: &MessageItem) -> Option<i64> {
+    match message_item {
+        MessageItem::Int64(i) => Some(*i),
+        MessageItem::UInt64(i) => i64::try_from(*i).ok(),
+        _ => None,
+    }
+}
+
 fn string_from_message_item(message_item: &MessageItem) -> Option<&str> {
     match message_item {
         MessageItem::Str(s) => Some(s),
@@ -145,67 +678,136 @@ fn vec_from_message_item(message_item: &MessageItem) -> Option<Vec<&str>> {
     Some(values)
 }
 
-fn get_attrs(dict: &MessageItemDict) -> Option<SongAttributes> {
-    debug!("processing dict: {:?}", dict);
-    let mut artist: Option<String> = None;
-    let mut title: Option<String> = None;
-    let mut url: Option<String> = None;
-
+// The (peeled) key-value pairs of the Metadata property included in the given dict.
+fn metadata_entries(dict: &MessageItemDict) -> Vec<(&str, &MessageItem)> {
+    let mut entries = vec![];
     let metadata_values = dict.iter().filter_map(|(key, value)| match key {
         MessageItem::Str(s) if s == "Metadata" => Some(value),
         _ => None,
     });
-
     for value in metadata_values {
         if let MessageItem::Variant(variant) = value {
-            let variant = variant.peel();
-            if let MessageItem::Dict(d) = variant {
+            if let MessageItem::Dict(d) = variant.peel() {
                 for (key, value) in d.iter() {
-                    let value = value.peel();
-                    match key {
-                        MessageItem::Str(s) if s == "xesam:artist" => {
-                            match vec_from_message_item(value) {
-                                Some(a) => {
-                                    artist = Some(a.join(", "));
-                                }
-                                None => {
-                                    warn!("Unable to parse artists from {:?}", value);
-                                }
-                            }
-                        }
-                        MessageItem::Str(s) if s == "xesam:title" => {
-                            match string_from_message_item(value) {
-                                Some(t) => {
-                                    title = Some(t.to_string());
-                                }
-                                None => {
-                                    warn!("Unable to parse title from {:?}", value);
-                                }
-                            }
-                        }
-                        MessageItem::Str(s) if s == "xesam:url" => {
-                            match string_from_message_item(value) {
-                                Some(u) => {
-                                    url = Some(u.to_string());
-                                }
-                                None => {
-                                    warn!("Unable to parse URL from {:?}", value);
-                                }
-                            }
-                        }
-                        _ => {
-                            // Nothing to do.
-                        }
-                    };
+                    if let MessageItem::Str(s) = key {
+                        entries.push((s.as_str(), value.peel()));
+                    }
+                }
+            }
+        }
+    }
+    entries
+}
+
+// Signals from some hosts (e.g. ads or browser media) are dropped before doing any further work.
+fn has_ignored_host(metadata: &[(&str, &MessageItem)]) -> bool {
+    let ignored_hosts = &settings::get().ignored_hosts;
+    if ignored_hosts.is_empty() {
+        return false;
+    }
+    url_from_metadata(metadata)
+        .and_then(|url| Url::parse(url).ok())
+        .is_some_and(|url| {
+            url.host_str()
+                .is_some_and(|host| ignored_hosts.iter().any(|h| h == host))
+        })
+}
+
+// Players put the URL into different fields, so we use the first of the configured keys that
+// contains a URL.
+fn url_from_metadata<'a>(metadata: &[(&str, &'a MessageItem)]) -> Option<&'a str> {
+    settings::get()
+        .url_metadata_keys
+        .iter()
+        .find_map(|url_key| {
+            let (_, value) = metadata.iter().find(|(key, _)| key == url_key)?;
+            let url = string_from_message_item(value);
+            if url.is_none() {
+                warn!("Unable to parse URL from {:?}", value);
+            }
+            url
+        })
+}
+
+// is_sent_by_spotify is only called if the URL alone doesn't tell whether the song is played by
+// Spotify, since it requires a D-Bus round trip.
+fn get_attrs<F>(dict: &MessageItemDict, is_sent_by_spotify: F) -> Option<SongAttributes>
+where
+    F: Fn() -> bool,
+{
+    debug!("processing dict: {:?}", dict);
+    let metadata = metadata_entries(dict);
+    if has_ignored_host(&metadata) {
+        return None;
+    }
+
+    let mut artist: Option<String> = None;
+    let mut title: Option<String> = None;
+    let url = url_from_metadata(&metadata).map(|url| url.to_string());
+    let mut length: Option<Duration> = None;
+    let mut track_id: Option<String> = None;
+
+    for (key, value) in metadata {
+        match key {
+            "xesam:artist" => {
+                // According to the MPRIS spec, xesam:artist is a list of strings,
+                // but some players send a single string instead.
+                let artists = vec_from_message_item(value)
+                    .or_else(|| string_from_message_item(value).map(|a| vec![a]));
+                match artists {
+                    Some(a) => {
+                        artist = Some(a.join(", "));
+                    }
+                    None => {
+                        warn!("Unable to parse artists from {:?}", value);
+                    }
                 }
             }
+            "xesam:title" => match string_from_message_item(value) {
+                Some(t) => {
+                    title = Some(t.to_string());
+                }
+                None => {
+                    warn!("Unable to parse title from {:?}", value);
+                }
+            },
+            "mpris:trackid" => {
+                let trackid = match value {
+                    MessageItem::ObjectPath(path) => Some(&**path),
+                    value => string_from_message_item(value),
+                };
+                track_id = trackid.and_then(track_id_from_trackid);
+            }
+            "mpris:length" => match int_from_message_item(value).and_then(length_from_micros) {
+                Some(l) => {
+                    length = Some(l);
+                }
+                None => {
+                    warn!("Unable to parse length from {:?}", value);
+                }
+            },
+            _ => {
+                // Nothing to do.
+            }
         };
     }
 
+    // A track ID means the event was emitted by Spotify, even if the URL looks unusual. Local files
+    // are played by all kinds of players, so we only consider them if they're played by Spotify.
+    let is_spotify_song = |url: &str| {
+        is_supported_url(url)
+            || track_id.is_some()
+            || (url.starts_with("file://") && is_sent_by_spotify())
+    };
     match url {
-        Some(url) if url.contains("open.spotify.com") => {
-            Some(SongAttributes { url, artist, title })
-        }
+        Some(url) if is_spotify_song(&url) => Some(SongAttributes {
+            url: config::normalize_url(&url).unwrap_or_else(|_| url.clone()),
+            raw_url: url,
+            artist,
+            title,
+            length,
+            track_id,
+        }),
         _ => {
             // if no URL exists, or the URL does not contain the spotify host, then the event was probably not emitted
             // by spotify and should be ignored.
@@ -213,11 +815,23 @@ fn get_attrs(dict: &MessageItemDict) -> Option<SongAttributes> {
         }
     }
 }
-#[derive(Debug)]
+
+// Local files played by the Spotify client don't have a Spotify URL, but a spotify:local: URI (or a
+// file:// URL, see get_attrs) instead.
+fn is_supported_url(url: &str) -> bool {
+    url.contains("open.spotify.com") || url.starts_with("spotify:local:")
+}
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct SongAttributes {
+    // The normalized URL, which is used to match songs.
     pub url: String,
+    // The URL exactly as we've received it from the player.
+    pub raw_url: String,
     pub artist: Option<String>,
     pub title: Option<String>,
+    pub length: Option<Duration>,
+    // Parsed from mpris:trackid, which is more reliable than the URL for some clients.
+    pub track_id: Option<String>,
 }
 
 impl Display for SongAttributes {
@@ -233,3 +847,218 @@ impl Display for SongAttributes {
         write!(f, "Artist: {}, Title: {}, URL: {}", artist, title, self.url)
     }
 }
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Decision {
+    // The song is blocked and the given action was applied.
+    Blocked(BlockAction),
+    NotBlocked,
+    Allowed,
+    // We're in allowlist mode and the song was skipped because it isn't allowed.
+    NotAllowed,
+    // The song has neither an artist nor a title and was skipped because of skip_unknown_metadata.
+    UnknownMetadata,
+    // The song should have been skipped, but was skipped too often recently.
+    LoopDetected,
+    // The song should have been skipped, but is allowed through once because it has already been
+    // skipped allow_after_skips times.
+    AllowedAfterRepeatedSkips,
+    // We're in allowlist mode and the song isn't allowed, but is played because allowlist mode has
+    // only been enabled recently.
+    Warmup,
+    // The song is blocked, but played because it comes up for the first time since audiowarden was
+    // started and skip_on_repeat is enabled.
+    FirstEncounter,
+    // The song matches a give_one_chance rule and is played because it hasn't been played before.
+    GivenOneChance,
+    // The song matches a give_one_chance rule, but is not skipped because it was played in full.
+    PlayedInFullBefore,
+    // Blocking has been disabled via the disable command.
+    Disabled,
+    // The current mode is off.
+    Off,
+    // We're within the quiet hours.
+    Quiet,
+    // Songs of this type (track or episode) are not considered, according to the settings.
+    IgnoredContentType,
+}
+
+impl Decision {
+    // What to do with the song, or None if it should just be played.
+    pub fn action(&self) -> Option<BlockAction> {
+        match self {
+            Decision::Blocked(action) => Some(*action),
+            Decision::NotAllowed | Decision::UnknownMetadata => Some(settings::get().block_action),
+            _ => None,
+        }
+    }
+}
+
+impl Display for Decision {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        let s = match self {
+            Decision::Blocked(BlockAction::Skip) => "[BLOCKED]",
+            Decision::Blocked(BlockAction::Pause) => "[BLOCKED, PAUSED]",
+            Decision::Blocked(BlockAction::Mute) => "[BLOCKED, MUTED]",
+            Decision::NotBlocked => "[NOT BLOCKED]",
+            Decision::Allowed => "[ALLOWED]",
+            Decision::NotAllowed => "[NOT ALLOWED]",
+            Decision::UnknownMetadata => "[UNKNOWN METADATA]",
+            Decision::LoopDetected => "[LOOP DETECTED, NOT SKIPPED]",
+            Decision::AllowedAfterRepeatedSkips => "[ALLOWED AFTER REPEATED SKIPS]",
+            Decision::Warmup => "[WARMUP]",
+            Decision::FirstEncounter => "[FIRST ENCOUNTER, NOT SKIPPED]",
+            Decision::GivenOneChance => "[GIVEN ONE CHANCE]",
+            Decision::PlayedInFullBefore => "[PLAYED IN FULL BEFORE]",
+            Decision::Disabled => "[DISABLED]",
+            Decision::Off => "[OFF]",
+            Decision::Quiet => "[QUIET]",
+            Decision::IgnoredContentType => "[IGNORED CONTENT TYPE]",
+        };
+        write!(f, "{}", s)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::blocklist::{BlockRule, RuleKind, RuleSource};
+
+    const URL: &str = "https://open.spotify.com/track/6CE6xXEI29e6X0noaNugIW";
+
+    fn song(url: &str, artist: Option<&str>, title: Option<&str>) -> SongAttributes {
+        SongAttributes {
+            url: url.to_string(),
+            raw_url: url.to_string(),
+            artist: artist.map(str::to_string),
+            title: title.map(str::to_string),
+            length: None,
+            track_id: None,
+        }
+    }
+
+    fn listed_songs(url: &str) -> Arc<BlockList> {
+        let mut block_list = BlockList::default();
+        block_list.insert(BlockRule {
+            kind: RuleKind::Url(url.to_string()),
+            source: RuleSource::Stdin { line_number: 1 },
+            give_one_chance: false,
+            action: None,
+        });
+        Arc::new(block_list)
+    }
+
+    fn decide_in_mode(song: &SongAttributes, mode: Mode, settings: &Settings) -> Decision {
+        let one_chance_songs = BTreeMap::new();
+        let context = DecisionContext {
+            settings,
+            blocking_enabled: true,
+            quiet: false,
+            mode,
+            one_chance_songs: &one_chance_songs,
+        };
+        decide_with(song, &context, |_| Ok(listed_songs(URL))).unwrap()
+    }
+
+    #[test]
+    fn decide_blocked() {
+        let song = song(URL, Some("artist"), Some("title"));
+        assert_eq!(
+            decide_in_mode(&song, Mode::Blocklist, &Settings::default()),
+            Decision::Blocked(BlockAction::Skip)
+        );
+    }
+
+    #[test]
+    fn decide_not_blocked() {
+        let song = song(
+            "https://open.spotify.com/track/4uLU6hMCjMI75M1A2tKUQC",
+            Some("artist"),
+            Some("title"),
+        );
+        assert_eq!(
+            decide_in_mode(&song, Mode::Blocklist, &Settings::default()),
+            Decision::NotBlocked
+        );
+    }
+
+    #[test]
+    fn decide_disabled() {
+        let one_chance_songs = BTreeMap::new();
+        let context = DecisionContext {
+            settings: &Settings::default(),
+            blocking_enabled: false,
+            quiet: false,
+            mode: Mode::Blocklist,
+            one_chance_songs: &one_chance_songs,
+        };
+        let decision = decide_with(&song(URL, None, None), &context, |_| {
+            panic!("the listed songs must not be loaded if blocking is disabled")
+        });
+        assert_eq!(decision.unwrap(), Decision::Disabled);
+    }
+
+    #[test]
+    fn decide_not_allowed() {
+        let song = song(
+            "https://open.spotify.com/track/4uLU6hMCjMI75M1A2tKUQC",
+            Some("artist"),
+            Some("title"),
+        );
+        assert_eq!(
+            decide_in_mode(&song, Mode::Allowlist, &Settings::default()),
+            Decision::NotAllowed
+        );
+    }
+
+    #[test]
+    fn decide_unknown_metadata() {
+        let song = song(
+            "https://open.spotify.com/track/4uLU6hMCjMI75M1A2tKUQC",
+            None,
+            Some(""),
+        );
+        let mut settings = Settings::default();
+        settings.skip_unknown_metadata = true;
+        assert_eq!(
+            decide_in_mode(&song, Mode::Blocklist, &settings),
+            Decision::UnknownMetadata
+        );
+        assert_eq!(
+            decide_in_mode(&song, Mode::Blocklist, &Settings::default()),
+            Decision::NotBlocked
+        );
+    }
+
+    #[test]
+    fn track_id_from_uri() {
+        assert_eq!(
+            track_id_from_trackid("spotify:track:6CE6xXEI29e6X0noaNugIW").as_deref(),
+            Some("6CE6xXEI29e6X0noaNugIW")
+        );
+    }
+
+    #[test]
+    fn track_id_from_object_path() {
+        assert_eq!(
+            track_id_from_trackid("/com/spotify/track/6CE6xXEI29e6X0noaNugIW").as_deref(),
+            Some("6CE6xXEI29e6X0noaNugIW")
+        );
+    }
+
+    #[test]
+    fn track_id_from_invalid_trackid() {
+        for trackid in [
+            "",
+            "spotify:track:",
+            "/com/spotify/track/",
+            "spotify:episode:6CE6xXEI29e6X0noaNugIW",
+            "/com/spotify/ad/6CE6xXEI29e6X0noaNugIW",
+            "/org/mpris/MediaPlayer2/TrackList/NoTrack",
+            "spotify:track:6CE6xXEI29e6X0noaNugIW/extra",
+            "6CE6xXEI29e6X0noaNugIW",
+        ] {
+            assert_eq!(track_id_from_trackid(trackid), None, "{}", trackid);
+        }
+    }
+}
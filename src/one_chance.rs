@@ -0,0 +1,123 @@
+use std::collections::BTreeMap;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use crate::blocklist::BlockAction;
+use crate::mpris::{Decision, SongAttributes};
+use crate::{mpris, state};
+
+// A song counts as played in full if it was played for at least this fraction of its length.
+const COMPLETION_RATIO: f64 = 0.9;
+
+// The player doesn't send signals while a song is playing, so we have to ask for the position.
+const POSITION_CHECK_INTERVAL: Duration = Duration::from_secs(1);
+
+// The song that is currently being given its one chance, if any.
+static CURRENT_CHANCE: Mutex<Option<Chance>> = Mutex::new(None);
+
+struct Chance {
+    url: String,
+    // Without the length, we can't tell whether the song was played in full.
+    length: Option<Duration>,
+    // The furthest playback position reported by the player so far.
+    max_position: Duration,
+    position_checked: Option<Instant>,
+}
+
+// Decides what to do with a song that matches a give_one_chance rule: The song is played the first
+// time it comes up, and is only skipped afterwards if it wasn't played in full that first time.
+pub fn decide(
+    url: &str,
+    action: BlockAction,
+    one_chance_songs: &BTreeMap<String, bool>,
+) -> Decision {
+    match one_chance_songs.get(url) {
+        None => Decision::GivenOneChance,
+        Some(true) => Decision::PlayedInFullBefore,
+        Some(false) if is_current_chance(url) => {
+            // The player usually emits multiple signals for the same song, we don't want to skip
+            // the song while it's still being given its chance.
+            Decision::GivenOneChance
+        }
+        Some(false) => Decision::Blocked(action),
+    }
+}
+
+fn is_current_chance(url: &str) -> bool {
+    CURRENT_CHANCE
+        .lock()
+        .unwrap()
+        .as_ref()
+        .is_some_and(|chance| chance.url == url)
+}
+
+// Must be called for every song that starts playing, so that we can tell whether the song that is
+// currently being given its chance has been played in full.
+pub fn song_started(song: &SongAttributes, decision: Decision) {
+    let mut current_chance = CURRENT_CHANCE.lock().unwrap();
+    if current_chance
+        .as_ref()
+        .is_some_and(|chance| chance.url == song.url)
+    {
+        return;
+    }
+    if let Some(chance) = current_chance.take() {
+        // By the time we receive the signal for the next song, the player reports the position
+        // within the next song, so we rely on the positions checked while the song was playing.
+        let played_in_full = chance
+            .length
+            .is_some_and(|length| chance.max_position >= length.mul_f64(COMPLETION_RATIO));
+        if played_in_full {
+            debug!("Song {} was played in full.", chance.url);
+            record(&chance.url, true);
+        }
+    }
+    if decision == Decision::GivenOneChance {
+        record(&song.url, false);
+        *current_chance = Some(Chance {
+            url: song.url.clone(),
+            length: song.length,
+            max_position: Duration::ZERO,
+            position_checked: None,
+        });
+    }
+}
+
+// Must be called regularly, so that we know how far the song that is currently being given its
+// chance has been played. Unlike the elapsed time, the position accounts for pausing and seeking.
+pub fn check_position() {
+    let url = {
+        let mut current_chance = CURRENT_CHANCE.lock().unwrap();
+        let chance = match current_chance.as_mut() {
+            Some(chance) => chance,
+            None => return,
+        };
+        if chance
+            .position_checked
+            .is_some_and(|checked| checked.elapsed() < POSITION_CHECK_INTERVAL)
+        {
+            return;
+        }
+        chance.position_checked = Some(Instant::now());
+        chance.url.clone()
+    };
+    let position = match mpris::player_position() {
+        Some(position) => position,
+        None => return,
+    };
+    let mut current_chance = CURRENT_CHANCE.lock().unwrap();
+    if let Some(chance) = current_chance.as_mut().filter(|chance| chance.url == url) {
+        chance.max_position = chance.max_position.max(position);
+    }
+}
+
+fn record(url: &str, played_in_full: bool) {
+    let result = state::update(|state| {
+        state
+            .one_chance_songs
+            .insert(url.to_string(), played_in_full);
+    });
+    if let Err(e) = result {
+        error!("Unable to save state: {}", e);
+    }
+}
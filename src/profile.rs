@@ -0,0 +1,26 @@
+use std::path::{Path, PathBuf};
+use std::sync::OnceLock;
+
+static PROFILE: OnceLock<String> = OnceLock::new();
+
+pub fn set_profile(name: &str) -> Result<(), String> {
+    if name.is_empty() || name == "." || name == ".." || name.contains('/') {
+        return Err(format!("Invalid profile name: {:?}", name));
+    }
+    PROFILE
+        .set(name.to_string())
+        .map_err(|_| "Profile has already been set.".to_string())
+}
+
+pub fn get_profile() -> Option<&'static str> {
+    PROFILE.get().map(|p| p.as_str())
+}
+
+// Each profile uses its own subdirectory, so that multiple instances of audiowarden with different
+// profiles can run in parallel without interfering with each other.
+pub fn namespaced_path(path: &Path) -> PathBuf {
+    match get_profile() {
+        Some(profile) => path.join("profiles").join(profile),
+        None => path.to_path_buf(),
+    }
+}
@@ -0,0 +1,80 @@
+use std::fmt::{Display, Formatter};
+
+use serde::{Deserialize, Serialize};
+
+use crate::settings;
+
+// A local time like "23:30", used for the quiet hours.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Deserialize, Serialize)]
+#[serde(try_from = "String", into = "String")]
+pub struct TimeOfDay {
+    minutes_since_midnight: u32,
+}
+
+impl TryFrom<String> for TimeOfDay {
+    type Error = String;
+
+    fn try_from(s: String) -> Result<Self, Self::Error> {
+        let invalid = || format!("invalid time '{}', expected HH:MM", s);
+        let (hours, minutes) = s.split_once(':').ok_or_else(invalid)?;
+        let hours: u32 = hours.parse().map_err(|_| invalid())?;
+        let minutes: u32 = minutes.parse().map_err(|_| invalid())?;
+        if hours > 23 || minutes > 59 {
+            return Err(invalid());
+        }
+        Ok(TimeOfDay {
+            minutes_since_midnight: hours * 60 + minutes,
+        })
+    }
+}
+
+impl From<TimeOfDay> for String {
+    fn from(time: TimeOfDay) -> Self {
+        time.to_string()
+    }
+}
+
+impl Display for TimeOfDay {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{:02}:{:02}",
+            self.minutes_since_midnight / 60,
+            self.minutes_since_midnight % 60
+        )
+    }
+}
+
+pub fn is_quiet_now() -> bool {
+    let settings = settings::get();
+    let (start, end) = match (settings.quiet_hours_start, settings.quiet_hours_end) {
+        (Some(start), Some(end)) => (start, end),
+        _ => return false,
+    };
+    let now = match local_time() {
+        Some(now) => now,
+        None => {
+            warn!("Unable to determine the local time, ignoring quiet hours.");
+            return false;
+        }
+    };
+    if start <= end {
+        start <= now && now < end
+    } else {
+        // The quiet hours span midnight, e.g. from 23:00 to 07:00.
+        start <= now || now < end
+    }
+}
+
+fn local_time() -> Option<TimeOfDay> {
+    // The standard library doesn't know about time zones, so we let libc convert the current time
+    // into the local time.
+    let mut tm: libc::tm = unsafe { std::mem::zeroed() };
+    let now = unsafe { libc::time(std::ptr::null_mut()) };
+    if unsafe { libc::localtime_r(&now, &mut tm) }.is_null() {
+        return None;
+    }
+    Some(TimeOfDay {
+        minutes_since_midnight: tm.tm_hour as u32 * 60 + tm.tm_min as u32,
+    })
+}
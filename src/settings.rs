@@ -0,0 +1,193 @@
+use std::fs;
+use std::io::ErrorKind;
+use std::path::PathBuf;
+use std::sync::OnceLock;
+
+use serde::de::IgnoredAny;
+use serde::{Deserialize, Serialize};
+
+use crate::blocklist::BlockAction;
+use crate::config;
+use crate::mode::Mode;
+use crate::quiet_hours::TimeOfDay;
+
+static SETTINGS: OnceLock<Settings> = OnceLock::new();
+
+// Set if the settings file exists, but couldn't be read or parsed, so that the defaults are used.
+static SETTINGS_ERROR: OnceLock<String> = OnceLock::new();
+
+#[derive(Debug, Deserialize, Serialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Settings {
+    // How long a client connected to the unix socket waits for the daemon to process its command
+    // before it receives an error response.
+    pub socket_response_timeout_ms: u64,
+    // Log a warning if no MPRIS signals have been received for this many seconds while Spotify
+    // is playing a song we haven't received a signal for. 0 disables the warning.
+    pub watchdog_timeout_secs: u64,
+    // Keep query params like '?si=…' in URLs instead of removing them before songs are matched.
+    pub keep_url_query: bool,
+    // The mode used upon start. Can be changed at runtime via the unix socket.
+    pub mode: Mode,
+    // Protects against config files that have grown out of control (e.g. because of a script
+    // that keeps appending the same songs): Entries beyond this limit are ignored.
+    pub max_entries_per_file: usize,
+    // Exit if the unix socket can't be opened, instead of running without it.
+    pub exit_on_socket_error: bool,
+    // A file with one word or phrase per line: All songs whose title contains one of them are
+    // skipped. Relative paths are relative to the config directory.
+    pub title_keywords_file: Option<PathBuf>,
+    // How many of the most recently received songs are kept for the recent_events command.
+    pub recent_events_capacity: usize,
+    // If the same song is skipped more than loop_detection_max_skips times within
+    // loop_detection_window_secs, we stop skipping it for loop_detection_cooldown_secs.
+    // 0 disables loop detection.
+    pub loop_detection_max_skips: usize,
+    pub loop_detection_window_secs: u64,
+    pub loop_detection_cooldown_secs: u64,
+    // Rewritten whenever the song changes, for status bars and similar tools. Relative paths are
+    // relative to the config directory.
+    pub status_file: Option<PathBuf>,
+    // D-Bus names of the players whose signals are processed. If empty, signals from all players
+    // are processed.
+    pub trusted_players: Vec<String>,
+    // After a song has been skipped this many times, it is allowed through once and the count
+    // starts over. 0 means songs are always skipped.
+    pub allow_after_skips: usize,
+    // Signals for songs whose URL has one of these hosts are ignored.
+    pub ignored_hosts: Vec<String>,
+    // The first songs after allowlist mode was enabled are allowed through, so that switching to
+    // allowlist mode doesn't abruptly skip everything.
+    pub allowlist_warmup: usize,
+    // If set, the same commands as via the unix socket are also accepted via HTTP on this port.
+    // Only bound to localhost.
+    pub http_api_port: Option<u16>,
+    // Keep the blocked songs in memory and only read the config files again once they have
+    // changed, instead of reading them for every song.
+    pub cache_blocked_songs: bool,
+    // No songs are skipped between these local times (e.g. overnight). Both must be set.
+    pub quiet_hours_start: Option<TimeOfDay>,
+    pub quiet_hours_end: Option<TimeOfDay>,
+    // Skip songs that have neither an artist nor a title (usually ads).
+    pub skip_unknown_metadata: bool,
+    // Blocked songs are only skipped if they have already come up before since audiowarden was
+    // started.
+    pub skip_on_repeat: bool,
+    // The metadata keys that may contain the song's URL, in the order in which they're tried.
+    pub url_metadata_keys: Vec<String>,
+    // What to do with blocked songs, unless the rule specifies another action.
+    pub block_action: BlockAction,
+    // Read the config files as soon as they change (via inotify), instead of when the next song
+    // starts, so that errors show up in the logs right away.
+    pub watch_config: bool,
+    // Log a summary of what has happened every this many seconds. 0 disables the summary.
+    pub summary_interval_secs: u64,
+    // Whether tracks and podcast episodes are matched against the rules at all.
+    pub consider_tracks: bool,
+    pub consider_episodes: bool,
+    // Wait this many seconds before connecting to D-Bus, e.g. when audiowarden is started at login before
+    // the session is fully set up.
+    pub startup_delay_secs: u64,
+    // The [[block]] entries are parsed by the config module instead, since they need to be
+    // re-read whenever they change.
+    #[serde(rename = "block", skip_serializing)]
+    _block: IgnoredAny,
+}
+
+impl Default for Settings {
+    fn default() -> Self {
+        Settings {
+            socket_response_timeout_ms: 10_000,
+            watchdog_timeout_secs: 1800,
+            keep_url_query: false,
+            mode: Mode::Blocklist,
+            max_entries_per_file: 100_000,
+            exit_on_socket_error: false,
+            title_keywords_file: None,
+            recent_events_capacity: 20,
+            loop_detection_max_skips: 5,
+            loop_detection_window_secs: 60,
+            loop_detection_cooldown_secs: 300,
+            status_file: None,
+            trusted_players: vec![],
+            allow_after_skips: 0,
+            ignored_hosts: vec![],
+            allowlist_warmup: 0,
+            http_api_port: None,
+            cache_blocked_songs: true,
+            quiet_hours_start: None,
+            quiet_hours_end: None,
+            skip_unknown_metadata: false,
+            skip_on_repeat: false,
+            url_metadata_keys: vec!["xesam:url".to_string()],
+            block_action: BlockAction::Skip,
+            watch_config: false,
+            summary_interval_secs: 0,
+            consider_tracks: true,
+            consider_episodes: true,
+            startup_delay_secs: 0,
+            _block: IgnoredAny,
+        }
+    }
+}
+
+pub fn get() -> &'static Settings {
+    SETTINGS.get_or_init(load_settings)
+}
+
+// Used by --check-config: Errors are otherwise only logged, since audiowarden keeps running with
+// the defaults.
+pub fn error() -> Option<&'static str> {
+    get();
+    SETTINGS_ERROR.get().map(String::as_str)
+}
+
+fn settings_file_path() -> Result<PathBuf, String> {
+    config::get_config_path().map(|path| path.join(config::TOML_CONFIG_FILENAME))
+}
+
+fn load_settings() -> Settings {
+    let path = match settings_file_path() {
+        Ok(path) => path,
+        Err(e) => {
+            warn!(
+                "Unable to determine path of settings file, using defaults: {}",
+                e
+            );
+            return Settings::default();
+        }
+    };
+    match fs::read_to_string(&path) {
+        Ok(content) => match toml::from_str(&content) {
+            Ok(settings) => {
+                info!("Loaded settings from {}", path.display());
+                settings
+            }
+            Err(e) => {
+                // TOML errors span multiple lines and end with a line break.
+                let message = format!(
+                    "Unable to parse settings file {}: {}",
+                    path.display(),
+                    e.to_string().trim_end()
+                );
+                error!("{}. Using defaults.", message);
+                let _ = SETTINGS_ERROR.set(message);
+                Settings::default()
+            }
+        },
+        Err(e) if e.kind() == ErrorKind::NotFound => {
+            // The settings file is optional: Most users will be fine with the defaults.
+            debug!(
+                "No settings file found at {}, using defaults.",
+                path.display()
+            );
+            Settings::default()
+        }
+        Err(e) => {
+            let message = format!("Unable to read settings file {}: {}", path.display(), e);
+            error!("{}. Using defaults.", message);
+            let _ = SETTINGS_ERROR.set(message);
+            Settings::default()
+        }
+    }
+}
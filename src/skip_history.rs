@@ -0,0 +1,217 @@
+use std::collections::{HashMap, VecDeque};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use crate::settings;
+
+// Kept in memory only, so everything is reset on restart.
+static SKIP_HISTORY: Mutex<Option<HashMap<String, TrackHistory>>> = Mutex::new(None);
+
+#[derive(Debug, Default)]
+struct TrackHistory {
+    // When this track has recently been skipped, oldest first.
+    recent_skips: VecDeque<Instant>,
+    // Set when the track was skipped too often in a short time.
+    paused_until: Option<Instant>,
+    // How often the track was skipped since it was last allowed through.
+    skip_count: usize,
+    // How often the track came up since audiowarden was started.
+    encounters: usize,
+    // The encounter in which the last skip was counted, and whether the track was allowed through
+    // in that encounter. The player sends multiple signals per track, which must neither count as
+    // multiple skips nor skip a track that has just been allowed through.
+    last_skip_decision: Option<(usize, bool)>,
+    // The encounter in which the track was last blocked, so that it's only counted once.
+    last_block: Option<usize>,
+    // The encounter in which the last skip was recorded for loop detection, and the result.
+    last_loop_check: Option<(usize, bool)>,
+}
+
+pub struct Encounter {
+    // How often the track came up since audiowarden was started, including this time.
+    pub count: usize,
+    // False if this is just another signal for the track that is already playing.
+    pub is_new: bool,
+}
+
+// The most recently seen track, so that multiple signals for the same track don't count as multiple
+// encounters.
+static LAST_SEEN_URL: Mutex<Option<String>> = Mutex::new(None);
+
+pub fn record_encounter(url: &str) -> Encounter {
+    let mut last_seen_url = LAST_SEEN_URL.lock().unwrap();
+    let is_new = last_seen_url.as_deref() != Some(url);
+    *last_seen_url = Some(url.to_string());
+    with_history(url, |history| {
+        if is_new {
+            history.encounters += 1;
+        }
+        Encounter {
+            count: history.encounters,
+            is_new,
+        }
+    })
+}
+
+// Returns true the first time the track is blocked during its current encounter.
+pub fn is_first_block(url: &str) -> bool {
+    with_history(url, |history| {
+        let is_first = history.last_block != Some(history.encounters);
+        history.last_block = Some(history.encounters);
+        is_first
+    })
+}
+
+// Returns true if the track has already been skipped allow_after_skips times and should therefore
+// be allowed through once, to avoid getting stuck with shuffle-heavy playlists. Requires
+// record_encounter to be called for every song first.
+pub fn allow_after_repeated_skips(url: &str) -> bool {
+    let threshold = settings::get().allow_after_skips;
+    if threshold == 0 {
+        return false;
+    }
+    with_history(url, |history| {
+        if let Some((encounter, allowed)) = history.last_skip_decision {
+            if encounter == history.encounters {
+                return allowed;
+            }
+        }
+        let allowed = if history.skip_count >= threshold {
+            history.skip_count = 0;
+            true
+        } else {
+            history.skip_count += 1;
+            false
+        };
+        history.last_skip_decision = Some((history.encounters, allowed));
+        allowed
+    })
+}
+
+fn with_history<F, T>(url: &str, f: F) -> T
+where
+    F: FnOnce(&mut TrackHistory) -> T,
+{
+    let mut skip_history = SKIP_HISTORY.lock().unwrap();
+    let history = skip_history
+        .get_or_insert_with(HashMap::new)
+        .entry(url.to_string())
+        .or_default();
+    f(history)
+}
+
+// Returns false if the track has been skipped so often in a short time that the player is
+// probably stuck in a loop (e.g. repeating a single blocked track). In that case, we stop skipping
+// this track for a while instead of sending Next over and over again. At most one skip is recorded
+// per encounter, so record_encounter must be called for every song first.
+pub fn record_skip_unless_looping(url: &str) -> bool {
+    let settings = settings::get();
+    if settings.loop_detection_max_skips == 0 {
+        return true;
+    }
+    let window = Duration::from_secs(settings.loop_detection_window_secs);
+    let cooldown = Duration::from_secs(settings.loop_detection_cooldown_secs);
+
+    with_history(url, |history| {
+        record_skip(
+            url,
+            history,
+            settings.loop_detection_max_skips,
+            window,
+            cooldown,
+        )
+    })
+}
+
+fn record_skip(
+    url: &str,
+    history: &mut TrackHistory,
+    max_skips: usize,
+    window: Duration,
+    cooldown: Duration,
+) -> bool {
+    if let Some((encounter, skipping)) = history.last_loop_check {
+        if encounter == history.encounters {
+            return skipping;
+        }
+    }
+    let skipping = check_loop(url, history, max_skips, window, cooldown);
+    history.last_loop_check = Some((history.encounters, skipping));
+    skipping
+}
+
+fn check_loop(
+    url: &str,
+    history: &mut TrackHistory,
+    max_skips: usize,
+    window: Duration,
+    cooldown: Duration,
+) -> bool {
+    let now = Instant::now();
+    if let Some(paused_until) = history.paused_until {
+        if now < paused_until {
+            return false;
+        }
+        history.paused_until = None;
+    }
+
+    while history
+        .recent_skips
+        .front()
+        .is_some_and(|skipped| now.duration_since(*skipped) > window)
+    {
+        history.recent_skips.pop_front();
+    }
+
+    if history.recent_skips.len() >= max_skips {
+        warn!(
+            "{} has been skipped {} times within {} seconds: The player is probably stuck in a \
+                loop, so we stop skipping this song for {} seconds.",
+            url,
+            history.recent_skips.len(),
+            window.as_secs(),
+            cooldown.as_secs()
+        );
+        history.recent_skips.clear();
+        history.paused_until = Some(now + cooldown);
+        return false;
+    }
+
+    history.recent_skips.push_back(now);
+    true
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const URL: &str = "https://open.spotify.com/track/4uLU6hMCjMI75M1A2tKUQC";
+    const WINDOW: Duration = Duration::from_secs(60);
+    const COOLDOWN: Duration = Duration::from_secs(300);
+
+    #[test]
+    fn record_skip_counts_once_per_encounter() {
+        let mut history = TrackHistory {
+            encounters: 1,
+            ..Default::default()
+        };
+        for _ in 0..10 {
+            assert!(record_skip(URL, &mut history, 5, WINDOW, COOLDOWN));
+        }
+        assert_eq!(history.recent_skips.len(), 1);
+    }
+
+    #[test]
+    fn record_skip_detects_loop_across_encounters() {
+        let mut history = TrackHistory::default();
+        for _ in 0..5 {
+            history.encounters += 1;
+            assert!(record_skip(URL, &mut history, 5, WINDOW, COOLDOWN));
+            assert!(record_skip(URL, &mut history, 5, WINDOW, COOLDOWN));
+        }
+        history.encounters += 1;
+        assert!(!record_skip(URL, &mut history, 5, WINDOW, COOLDOWN));
+        assert!(!record_skip(URL, &mut history, 5, WINDOW, COOLDOWN));
+        assert!(history.paused_until.is_some());
+    }
+}
@@ -0,0 +1,124 @@
+use std::collections::BTreeMap;
+use std::fs;
+use std::io::{Error, ErrorKind};
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::{env, io};
+
+use serde::{Deserialize, Serialize};
+
+use crate::mpris::SongAttributes;
+use crate::{profile, APPLICATION_NAME};
+
+const STATE_FILENAME: &str = "state.toml";
+
+// Cached in memory, so that we don't need to read the state file for every MPRIS message.
+static STATE: Mutex<Option<State>> = Mutex::new(None);
+
+// Unlike the settings, the state is modified by audiowarden itself and persisted across restarts.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct State {
+    pub blocking_enabled: bool,
+    // Songs matched by a give_one_chance rule that have already been played once, and whether
+    // they were played in full.
+    pub one_chance_songs: BTreeMap<String, bool>,
+    // The song we've most recently received a signal for.
+    pub last_song: Option<SongAttributes>,
+    // How many songs have been skipped since audiowarden was installed.
+    pub songs_skipped: u64,
+}
+
+impl Default for State {
+    fn default() -> Self {
+        State {
+            blocking_enabled: true,
+            one_chance_songs: BTreeMap::new(),
+            last_song: None,
+            songs_skipped: 0,
+        }
+    }
+}
+
+pub fn get() -> State {
+    let mut state = STATE.lock().unwrap();
+    state.get_or_insert_with(load_state).clone()
+}
+
+pub fn update<F>(f: F) -> io::Result<()>
+where
+    F: FnOnce(&mut State),
+{
+    let mut state = STATE.lock().unwrap();
+    let state = state.get_or_insert_with(load_state);
+    f(state);
+    save_state(state)
+}
+
+pub fn get_state_path() -> Result<PathBuf, String> {
+    let path = if let Ok(state_dir) = env::var("STATE_DIRECTORY") {
+        // STATE_DIRECTORY is set if this application runs via systemd: More details here:
+        // https://www.freedesktop.org/software/systemd/man/latest/systemd.exec.html#RuntimeDirectory=
+        Path::new(&state_dir).to_path_buf()
+    } else if let Ok(xdg_state_home) = env::var("XDG_STATE_HOME") {
+        Path::new(&xdg_state_home).join(APPLICATION_NAME)
+    } else if let Ok(home) = env::var("HOME") {
+        Path::new(&home)
+            .join(".local")
+            .join("state")
+            .join(APPLICATION_NAME)
+    } else {
+        return Err(
+            "None of the environment vars STATE_DIRECTORY, XDG_STATE_HOME or HOME is set."
+                .to_string(),
+        );
+    };
+
+    Ok(profile::namespaced_path(&path))
+}
+
+fn load_state() -> State {
+    let path = match get_state_path() {
+        Ok(path) => path.join(STATE_FILENAME),
+        Err(e) => {
+            warn!(
+                "Unable to determine state directory, using default state: {}",
+                e
+            );
+            return State::default();
+        }
+    };
+    match fs::read_to_string(&path) {
+        Ok(content) => match toml::from_str(&content) {
+            Ok(state) => state,
+            Err(e) => {
+                error!(
+                    "Unable to parse state file {}, using default state: {}",
+                    path.display(),
+                    e
+                );
+                State::default()
+            }
+        },
+        Err(e) if e.kind() == ErrorKind::NotFound => State::default(),
+        Err(e) => {
+            error!(
+                "Unable to read state file {}, using default state: {}",
+                path.display(),
+                e
+            );
+            State::default()
+        }
+    }
+}
+
+fn save_state(state: &State) -> io::Result<()> {
+    let path = get_state_path().map_err(|e| Error::new(ErrorKind::NotFound, e))?;
+    fs::create_dir_all(&path)?;
+    let content = toml::to_string(state).map_err(|e| Error::new(ErrorKind::InvalidData, e))?;
+    // Write to a temporary file first, so that we never end up with a partially written state
+    // file.
+    let tmp_path = path.join(format!("{}.tmp", STATE_FILENAME));
+    fs::write(&tmp_path, content)?;
+    fs::rename(&tmp_path, path.join(STATE_FILENAME))
+}
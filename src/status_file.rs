@@ -0,0 +1,43 @@
+use std::fs;
+use std::io;
+use std::path::Path;
+
+use crate::mpris::{Decision, SongAttributes};
+use crate::{config, settings};
+
+// Status bars like waybar or polybar can simply read this file to show the current song.
+pub fn update_status_file(song: &SongAttributes, decision: Decision) {
+    let path = match &settings::get().status_file {
+        Some(path) => path,
+        None => return,
+    };
+    // Relative paths are relative to the config directory.
+    let path = match config::get_config_path() {
+        Ok(config_path) => config_path.join(path),
+        Err(_) => path.clone(),
+    };
+    // Only true if the song is actually skipped, paused or muted.
+    let blocked = decision.action().is_some();
+    let content = format!(
+        "artist={}\ntitle={}\nurl={}\nblocked={}\n",
+        single_line(song.artist.as_deref().unwrap_or_default()),
+        single_line(song.title.as_deref().unwrap_or_default()),
+        single_line(&song.url),
+        blocked
+    );
+    if let Err(e) = write_atomically(&path, &content) {
+        warn!("Unable to write status file {}: {:?}", path.display(), e);
+    }
+}
+
+fn single_line(s: &str) -> String {
+    s.replace(['\n', '\r'], " ")
+}
+
+// Readers should never see a partially written file.
+fn write_atomically(path: &Path, content: &str) -> io::Result<()> {
+    let mut tmp_path = path.as_os_str().to_owned();
+    tmp_path.push(".tmp");
+    fs::write(&tmp_path, content)?;
+    fs::rename(&tmp_path, path)
+}
@@ -0,0 +1,55 @@
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::thread;
+use std::time::Duration;
+
+use crate::settings;
+
+// Counted since the last summary was logged.
+static SONGS_SEEN: AtomicUsize = AtomicUsize::new(0);
+static SONGS_BLOCKED: AtomicUsize = AtomicUsize::new(0);
+static CONFIG_RELOADS: AtomicUsize = AtomicUsize::new(0);
+static ERRORS: AtomicUsize = AtomicUsize::new(0);
+
+pub fn song_seen() {
+    SONGS_SEEN.fetch_add(1, Ordering::Relaxed);
+}
+
+pub fn song_blocked() {
+    SONGS_BLOCKED.fetch_add(1, Ordering::Relaxed);
+}
+
+pub fn config_reloaded() {
+    CONFIG_RELOADS.fetch_add(1, Ordering::Relaxed);
+}
+
+pub fn error_occurred() {
+    ERRORS.fetch_add(1, Ordering::Relaxed);
+}
+
+// Logs a summary every summary_interval_secs, which is less noisy than the per-song logs.
+pub fn start() {
+    let interval_secs = settings::get().summary_interval_secs;
+    if interval_secs == 0 {
+        return;
+    }
+    thread::spawn(move || loop {
+        thread::sleep(Duration::from_secs(interval_secs));
+        let songs_seen = SONGS_SEEN.swap(0, Ordering::Relaxed);
+        let songs_blocked = SONGS_BLOCKED.swap(0, Ordering::Relaxed);
+        let config_reloads = CONFIG_RELOADS.swap(0, Ordering::Relaxed);
+        let errors = ERRORS.swap(0, Ordering::Relaxed);
+        info!(
+            interval_secs,
+            songs_seen,
+            songs_blocked,
+            config_reloads,
+            errors;
+            "Summary of the last {} seconds: {} songs seen, {} blocked, {} config reloads, {} errors.",
+            interval_secs,
+            songs_seen,
+            songs_blocked,
+            config_reloads,
+            errors
+        );
+    });
+}